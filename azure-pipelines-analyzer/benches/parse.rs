@@ -0,0 +1,37 @@
+use azure_pipelines_analyzer::syntax::parse;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+fn inputs() -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "directive_parameters",
+            format!("%DIR{}\n", " parameter".repeat(10_000)),
+        ),
+        (
+            "directive_comment",
+            format!("%YAML 1.2 #{}\n", "comment ".repeat(10_000)),
+        ),
+        (
+            "tag_prefix",
+            format!(
+                "%TAG !yaml! tag:example.com,2024:{}\n",
+                "path/segment%20".repeat(10_000)
+            ),
+        ),
+        ("multibyte", format!("%DIR{}\n", " ภาษา".repeat(10_000))),
+    ]
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| parse(black_box(input.as_bytes())))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
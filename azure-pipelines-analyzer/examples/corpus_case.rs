@@ -0,0 +1,76 @@
+//! Adds a pipeline file to the golden-file corpus in `tests/corpus` and records its snapshot.
+//!
+//! Usage: `cargo run --example corpus_case -- <path> [name]`
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+fn main() {
+    let mut args = env::args_os().skip(1);
+    let Some(source) = args.next().map(PathBuf::from) else {
+        eprintln!("usage: corpus_case <path> [name]");
+        process::exit(2);
+    };
+    let name = match args.next() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let name = sanitize(&name);
+    if name.is_empty() {
+        eprintln!("corpus case name must not be empty");
+        process::exit(2);
+    }
+    // Keep the input's extension, since the corpus test matches both.
+    let extension = match source.extension() {
+        Some(extension) if extension == "yaml" => "yaml",
+        _ => "yml",
+    };
+
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dest = manifest_dir
+        .join("tests/corpus")
+        .join(format!("{name}.{extension}"));
+    if dest.exists() {
+        eprintln!("corpus case '{}' already exists", dest.display());
+        process::exit(1);
+    }
+
+    if let Err(err) = fs::copy(&source, &dest) {
+        eprintln!("failed to copy '{}': {err}", source.display());
+        process::exit(1);
+    }
+    println!("added '{}'", dest.display());
+
+    // Write the new snapshot as pending, so it can be checked with `cargo insta review` before being accepted.
+    let status = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["test", "--test", "corpus"])
+        .current_dir(manifest_dir)
+        .env("INSTA_UPDATE", "new")
+        .env("INSTA_FORCE_PASS", "1")
+        .status();
+    match status {
+        Ok(status) if status.success() => {
+            println!("review the new snapshot with `cargo insta review`")
+        }
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(err) => {
+            eprintln!("failed to run tests: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|ch| match ch {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => ch.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect()
+}
@@ -0,0 +1,15 @@
+#![no_main]
+
+use azure_pipelines_analyzer::syntax::{parse, Encoding};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let parse = parse(data);
+
+    // The tree must be lossless.
+    if let Ok(text) = std::str::from_utf8(data) {
+        if Encoding::detect(data) == Encoding::Utf8 {
+            assert_eq!(parse.text(), text);
+        }
+    }
+});
@@ -0,0 +1,164 @@
+//! Generates documents from YAML-like fragments, which reach much deeper into the grammar than arbitrary bytes.
+
+#![no_main]
+
+use std::fmt::{self, Write};
+
+use arbitrary::Arbitrary;
+use azure_pipelines_analyzer::syntax::{parse, Encoding};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+enum Fragment {
+    Directive(DirectiveName),
+    TagHandle(TagHandle),
+    Anchor(Word),
+    Alias(Word),
+    Comment(String),
+    Word(Word),
+    Indicator(Indicator),
+    Space(u8),
+    Tab,
+    LineBreak(LineBreak),
+    Raw(String),
+}
+
+#[derive(Debug, Arbitrary)]
+enum DirectiveName {
+    Yaml,
+    Tag,
+    Reserved(Word),
+}
+
+#[derive(Debug, Arbitrary)]
+enum TagHandle {
+    Primary,
+    Secondary,
+    Named(Word),
+    Verbatim(String),
+}
+
+#[derive(Debug, Arbitrary)]
+enum Indicator {
+    Sequence,
+    Mapping,
+    Key,
+    Value,
+    Entry,
+    FlowSequenceStart,
+    FlowSequenceEnd,
+    FlowMappingStart,
+    FlowMappingEnd,
+    SingleQuote,
+    DoubleQuote,
+    Literal,
+    Folded,
+    DocumentStart,
+    DocumentEnd,
+}
+
+#[derive(Debug, Arbitrary)]
+enum LineBreak {
+    Lf,
+    Cr,
+    CrLf,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Word(u8, u8);
+
+fuzz_target!(|fragments: Vec<Fragment>| {
+    let mut text = String::new();
+    for fragment in &fragments {
+        write!(text, "{fragment}").unwrap();
+    }
+
+    let parse = parse(text.as_bytes());
+
+    // The tree must be lossless. Raw fragments may contain nul characters, which are detected as UTF-16 or UTF-32.
+    if Encoding::detect(text.as_bytes()) == Encoding::Utf8 {
+        assert_eq!(parse.text(), text);
+    }
+});
+
+impl fmt::Display for Fragment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fragment::Directive(name) => write!(f, "%{name}"),
+            Fragment::TagHandle(handle) => write!(f, "{handle}"),
+            Fragment::Anchor(name) => write!(f, "&{name}"),
+            Fragment::Alias(name) => write!(f, "*{name}"),
+            Fragment::Comment(text) => write!(f, "#{text}"),
+            Fragment::Word(word) => write!(f, "{word}"),
+            Fragment::Indicator(indicator) => write!(f, "{indicator}"),
+            Fragment::Space(count) => write!(f, "{:1$}", "", usize::from(count % 8)),
+            Fragment::Tab => write!(f, "\t"),
+            Fragment::LineBreak(LineBreak::Lf) => write!(f, "\n"),
+            Fragment::LineBreak(LineBreak::Cr) => write!(f, "\r"),
+            Fragment::LineBreak(LineBreak::CrLf) => write!(f, "\r\n"),
+            Fragment::Raw(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+impl fmt::Display for DirectiveName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirectiveName::Yaml => write!(f, "YAML"),
+            DirectiveName::Tag => write!(f, "TAG"),
+            DirectiveName::Reserved(word) => write!(f, "{word}"),
+        }
+    }
+}
+
+impl fmt::Display for TagHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagHandle::Primary => write!(f, "!"),
+            TagHandle::Secondary => write!(f, "!!"),
+            TagHandle::Named(word) => write!(f, "!{word}!"),
+            TagHandle::Verbatim(uri) => write!(f, "!<{uri}>"),
+        }
+    }
+}
+
+impl fmt::Display for Indicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Indicator::Sequence => "- ",
+            Indicator::Mapping => ": ",
+            Indicator::Key => "? ",
+            Indicator::Value => ":",
+            Indicator::Entry => ",",
+            Indicator::FlowSequenceStart => "[",
+            Indicator::FlowSequenceEnd => "]",
+            Indicator::FlowMappingStart => "{",
+            Indicator::FlowMappingEnd => "}",
+            Indicator::SingleQuote => "'",
+            Indicator::DoubleQuote => "\"",
+            Indicator::Literal => "|",
+            Indicator::Folded => ">",
+            Indicator::DocumentStart => "---",
+            Indicator::DocumentEnd => "...",
+        })
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WORDS: &[&str] = &[
+            "1.2",
+            "a",
+            "foo",
+            "tag:yaml.org,2002:",
+            "task",
+            "steps",
+            "ภาษา",
+            "%20",
+            "-",
+            "_",
+        ];
+        let word = WORDS[usize::from(self.0) % WORDS.len()];
+        write!(f, "{}", word.repeat(usize::from(self.1 % 4) + 1))
+    }
+}
@@ -0,0 +1,455 @@
+//! A high-level entry point for checking pipeline files.
+//!
+//! [`Analyzer`] and [`Report`] are the recommended entry point for downstream tools. The lower-level
+//! [`SourceDatabase`], [`syntax`](crate::syntax), [`style`](crate::style), [`config`](crate::config) and
+//! [`workspace`](crate::workspace) APIs are also public, but are more likely to change as the parser grows.
+//!
+//! Reports and their diagnostics refer to files by [`FileId`], which is only meaningful to the [`Analyzer`] which
+//! produced them. Tools which store or exchange reports should identify files by [`Report::path`] instead.
+//!
+//! The parser does not yet understand the document grammar, only directives, so syntax errors are not reported
+//! through this API. Only errors which do not depend on the grammar, such as invalid encodings, are reported.
+
+use std::{
+    borrow::Cow,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use globset::GlobSet;
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    isolation::isolate,
+    source::normalize,
+    style::{self, StyleConfig},
+    syntax::ParseError,
+    workspace::{self, DiscoveryError},
+    AnalysisObserver, Diagnostic, FileId, LineEnding, Phase, PhaseEvent, Severity, SourceDatabase,
+};
+
+/// Checks files against a configuration, reusing state between files where possible.
+#[derive(Debug)]
+pub struct Analyzer {
+    root: PathBuf,
+    config: Config,
+    /// The compiled `paths` of each of `config.overrides`.
+    overrides: Vec<GlobSet>,
+    db: SourceDatabase,
+}
+
+/// The result of checking a single file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    file: FileId,
+    path: PathBuf,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A description of what this build of the analyzer supports, for tools which need to adapt to different versions.
+///
+/// New fields may be added as the analyzer gains features, so consumers should ignore fields they do not recognize.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The version of the `azure-pipelines-analyzer` crate.
+    pub version: &'static str,
+    /// The style rules which can be configured, identified by their configuration key.
+    pub style_rules: Vec<&'static str>,
+    /// The optional cargo features enabled in this build.
+    pub features: Vec<&'static str>,
+}
+
+impl Analyzer {
+    /// Creates an analyzer for files in the workspace at `root`.
+    ///
+    /// As with [`discover`](workspace::discover), the `paths` of each override are matched against paths relative to
+    /// `root`. Paths outside `root` are matched as they were given. Returns an error if any pattern is invalid.
+    pub fn new(root: impl Into<PathBuf>, config: Config) -> Result<Self, DiscoveryError> {
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|o| workspace::build_glob_set(&o.paths))
+            .collect::<Result<_, _>>()?;
+        Ok(Analyzer {
+            root: normalize(&root.into()),
+            config,
+            overrides,
+            db: SourceDatabase::new(),
+        })
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            version: env!("CARGO_PKG_VERSION"),
+            style_rules: vec![
+                "max-line-length",
+                "max-file-lines",
+                "line-ending",
+                "require-utf8",
+                "trailing-newline",
+            ],
+            features: vec![
+                #[cfg(feature = "tracing")]
+                "tracing",
+            ],
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Sets an observer which is notified as each phase of checking a file finishes.
+    pub fn set_observer(&mut self, observer: Arc<dyn AnalysisObserver>) {
+        self.db.set_observer(observer);
+    }
+
+    /// Reads and checks the file at `path`.
+    pub fn check_file(&mut self, path: impl Into<PathBuf>) -> io::Result<Report> {
+        let id = self.db.load(path)?;
+        Ok(self.check(id))
+    }
+
+    /// Checks the given file contents, without reading from disk. The path is used to identify the file in the report.
+    pub fn check_source(
+        &mut self,
+        path: impl Into<PathBuf>,
+        contents: impl Into<Arc<[u8]>>,
+    ) -> Report {
+        let id = self.db.set_file(path, contents);
+        self.check(id)
+    }
+
+    fn check(&mut self, id: FileId) -> Report {
+        let contents = self.db.contents(id);
+        let decoded = self.db.decode(id, &contents);
+        let parse = self.db.parse_decoded(id, &decoded);
+        // todo: report syntax errors once the parser covers the document grammar. Until then, every pipeline which
+        // does not start with a directive would have one.
+        let mut diagnostics: Vec<_> = parse
+            .errors()
+            .iter()
+            .filter(|diagnostic| !is_syntax_error(diagnostic))
+            .cloned()
+            .collect();
+
+        // Files which could not be decoded already have an error from the parser.
+        if let Ok(text) = &decoded.text {
+            let config = self.style_config(self.db.path(id));
+            let start = Instant::now();
+            let style = isolate(|| style::check(text, decoded.encoding, &config))
+                .unwrap_or_else(|panic| vec![panic.into_diagnostic("linting")]);
+            if let Some(observer) = self.db.observer() {
+                observer.phase_finished(&PhaseEvent {
+                    file: Some(id),
+                    phase: Phase::Lint,
+                    elapsed: start.elapsed(),
+                    input_len: text.len(),
+                    diagnostics: style.len(),
+                });
+            }
+            diagnostics.extend(style.into_iter().map(|diagnostic| diagnostic.with_file(id)));
+
+            if let Some(ending) = config.line_ending.or_else(|| LineEnding::detect(text)) {
+                diagnostics = diagnostics
+                    .into_iter()
+                    .map(|diagnostic| diagnostic.with_line_ending(ending))
+                    .collect();
+            }
+        }
+
+        Report {
+            file: id,
+            path: self.db.path(id).to_owned(),
+            diagnostics,
+        }
+    }
+
+    /// Gets the style configuration for `path`, applying any matching overrides.
+    fn style_config(&self, path: &Path) -> Cow<'_, StyleConfig> {
+        let path = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut config = Cow::Borrowed(&self.config.style);
+        for (paths, o) in self.overrides.iter().zip(&self.config.overrides) {
+            if paths.is_match(path) {
+                o.style.apply(config.to_mut());
+            }
+        }
+        config
+    }
+}
+
+fn is_syntax_error(diagnostic: &Diagnostic) -> bool {
+    match diagnostic.parse_error() {
+        Some(ParseError::InvalidEncoding { .. }) | None => false,
+        Some(_) => true,
+    }
+}
+
+impl Report {
+    /// Gets the id of the checked file, which diagnostics use to refer to it. The id is only meaningful to the
+    /// [`Analyzer`] which produced this report.
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Returns `true` if any diagnostic has [`Severity::Error`].
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity() == Severity::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Mutex};
+
+    use super::*;
+    use crate::{
+        config::Override,
+        style::{StyleConfig, StyleOverrides},
+        syntax::Encoding,
+        EditSet,
+    };
+
+    #[test]
+    fn check_source() {
+        let mut analyzer = Analyzer::new(".", Config::default()).unwrap();
+
+        let report = analyzer.check_source("valid.yml", b"%YAML 1.2\n".as_slice());
+        assert_eq!(report.path(), Path::new("valid.yml"));
+        assert!(report.diagnostics().is_empty());
+        assert!(!report.has_errors());
+
+        let report = analyzer.check_source("invalid.yml", b"\xff".as_slice());
+        assert!(report.has_errors());
+        assert_eq!(report.diagnostics()[0].file(), Some(report.file()));
+
+        // The parser only covers directives so far, so syntax errors are not reported.
+        let report = analyzer.check_source(
+            "azure-pipelines.yml",
+            b"trigger:\n- main\n\nsteps:\n- script: echo\n".as_slice(),
+        );
+        assert!(report.diagnostics().is_empty());
+        assert!(analyzer
+            .check_source("directive.yml", b"%YAML 1.x\n".as_slice())
+            .diagnostics()
+            .is_empty());
+
+        let contents = Encoding::Utf16Le.encode("%YAML 1.2\n");
+        let report = analyzer.check_source("utf16.yml", contents);
+        assert!(!report.has_errors());
+        assert_eq!(report.diagnostics().len(), 1);
+        assert_eq!(
+            report.diagnostics()[0].message(),
+            "file is encoded as utf-16le, but Azure DevOps expects utf-8"
+        );
+    }
+
+    #[test]
+    fn capabilities() {
+        let capabilities = Analyzer::capabilities();
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            capabilities.features.contains(&"tracing"),
+            cfg!(feature = "tracing")
+        );
+
+        // Style rules are named after their configuration keys.
+        let style = toml::Table::try_from(StyleConfig {
+            line_ending: Some(LineEnding::Lf),
+            ..StyleConfig::default()
+        })
+        .unwrap();
+        assert!(capabilities
+            .style_rules
+            .iter()
+            .all(|rule| style.contains_key(*rule)));
+        assert_eq!(capabilities.style_rules.len(), style.len());
+    }
+
+    #[test]
+    fn style() {
+        let mut config = Config::default();
+        config.style.max_line_length = 5;
+        config.style.trailing_newline = false;
+        let mut analyzer = Analyzer::new(".", config).unwrap();
+
+        let report = analyzer.check_source("long.yml", b"%YAML 1.2".as_slice());
+        assert!(!report.has_errors());
+        assert_eq!(report.diagnostics().len(), 1);
+        assert_eq!(report.diagnostics()[0].severity(), Severity::Warning);
+        assert_eq!(report.diagnostics()[0].file(), Some(report.file()));
+    }
+
+    #[test]
+    fn fix_line_endings() {
+        let mut analyzer = Analyzer::new(".", Config::default()).unwrap();
+        let text = "%YAML 1.2\r\n%TAG ! !foo";
+
+        let report = analyzer.check_source("crlf.yml", text.as_bytes());
+        let fix = &report.diagnostics().last().unwrap().fixes()[0];
+        assert_eq!(fix.message(), "add a line break");
+        let edits = EditSet::new(fix.edits().to_vec()).unwrap();
+        assert_eq!(edits.apply(text), "%YAML 1.2\r\n%TAG ! !foo\r\n");
+
+        // Files which only use `\r` have no detected line ending, but keep their line breaks.
+        let text = "%YAML 1.2\r%TAG ! !foo";
+        let report = analyzer.check_source("cr.yml", text.as_bytes());
+        let edits = EditSet::new(report.diagnostics()[0].fixes()[0].edits().to_vec()).unwrap();
+        assert_eq!(edits.apply(text), "%YAML 1.2\r%TAG ! !foo\r");
+
+        // A configured line ending takes precedence.
+        let mut config = Config::default();
+        config.style.line_ending = Some(LineEnding::Lf);
+        let mut analyzer = Analyzer::new(".", config).unwrap();
+        let report = analyzer.check_source("crlf.yml", text.as_bytes());
+        for diagnostic in report.diagnostics() {
+            let edits = EditSet::new(diagnostic.fixes()[0].edits().to_vec()).unwrap();
+            assert!(!edits.apply(text).ends_with("\r\n"));
+        }
+    }
+
+    #[test]
+    fn overrides() {
+        let mut config = Config::default();
+        config.style.max_line_length = 5;
+        config.overrides = vec![
+            Override {
+                paths: vec!["legacy/**".to_owned()],
+                style: StyleOverrides {
+                    max_line_length: Some(20),
+                    ..StyleOverrides::default()
+                },
+            },
+            Override {
+                paths: vec!["legacy/strict/*.yml".to_owned()],
+                style: StyleOverrides {
+                    max_line_length: Some(8),
+                    ..StyleOverrides::default()
+                },
+            },
+        ];
+        let mut analyzer = Analyzer::new(".", config).unwrap();
+
+        let text = b"%YAML 1.2 # comment\n".as_slice();
+        let report = analyzer.check_source("azure-pipelines.yml", text);
+        assert_eq!(
+            report.diagnostics()[0].message(),
+            "line is 19 characters long, exceeding the maximum of 5"
+        );
+        assert!(analyzer
+            .check_source("./legacy/build.yml", text)
+            .diagnostics()
+            .is_empty());
+        assert_eq!(
+            analyzer
+                .check_source("legacy/strict/build.yml", text)
+                .diagnostics()[0]
+                .message(),
+            "line is 19 characters long, exceeding the maximum of 8"
+        );
+    }
+
+    #[test]
+    fn observer() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(Phase, usize)>>);
+
+        impl AnalysisObserver for Recorder {
+            fn phase_finished(&self, event: &PhaseEvent) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((event.phase, event.diagnostics));
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let mut analyzer = Analyzer::new(".", Config::default()).unwrap();
+        analyzer.set_observer(recorder.clone());
+
+        analyzer.check_source("lint.yml", b"%YAML 1.2".as_slice());
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [(Phase::Decode, 0), (Phase::Parse, 0), (Phase::Lint, 1)]
+        );
+    }
+
+    #[test]
+    fn check_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("azure-pipelines.yml");
+        fs::write(&path, "%YAML 1.2").unwrap();
+
+        let mut analyzer = Analyzer::new(dir.path(), Config::default()).unwrap();
+        let report = analyzer.check_file(&path).unwrap();
+        assert_eq!(report.path(), path);
+        assert!(!report.has_errors());
+
+        fs::remove_file(&path).unwrap();
+        assert!(analyzer.check_file(&path).is_err());
+    }
+
+    #[test]
+    fn check_file_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("legacy");
+        fs::create_dir(&legacy).unwrap();
+        let text = "%YAML 1.2 # comment\n";
+        fs::write(dir.path().join("azure-pipelines.yml"), text).unwrap();
+        fs::write(legacy.join("build.yml"), text).unwrap();
+
+        let mut config = Config::default();
+        config.style.max_line_length = 5;
+        config.overrides = vec![Override {
+            paths: vec!["legacy/**".to_owned()],
+            style: StyleOverrides {
+                max_line_length: Some(20),
+                ..StyleOverrides::default()
+            },
+        }];
+        let mut analyzer = Analyzer::new(dir.path(), config.clone()).unwrap();
+
+        // Discovered paths are absolute, but overrides match them relative to the root.
+        let files = workspace::discover(dir.path(), &config.discovery).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            analyzer.check_file(&files[0].path).unwrap().diagnostics()[0].message(),
+            "line is 19 characters long, exceeding the maximum of 5"
+        );
+        assert!(analyzer
+            .check_file(legacy.join("build.yml"))
+            .unwrap()
+            .diagnostics()
+            .is_empty());
+    }
+
+    #[test]
+    fn invalid_override() {
+        let config = Config {
+            overrides: vec![Override {
+                paths: vec!["legacy/[".to_owned()],
+                style: StyleOverrides::default(),
+            }],
+            ..Config::default()
+        };
+        assert!(matches!(
+            Analyzer::new(".", config),
+            Err(DiscoveryError::Glob(_))
+        ));
+    }
+}
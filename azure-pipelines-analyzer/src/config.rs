@@ -0,0 +1,369 @@
+//! Layered configuration, loaded from `.azp-analyzer.toml` files.
+//!
+//! Configuration files are merged in order of increasing precedence: the user's home directory, then each directory
+//! from the repository root down to the directory being analyzed. Tables are merged key by key, while other values,
+//! including arrays, are replaced entirely by later files.
+//!
+//! Settings for some files can be changed with `[[overrides]]` sections, which apply to files matching any of their
+//! `paths`. Later overrides take precedence over earlier ones:
+//!
+//! ```toml
+//! [[overrides]]
+//! paths = ["legacy/**"]
+//! style.max-line-length = 240
+//! ```
+
+use std::{
+    env,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use toml::Table;
+
+use crate::{
+    style::{StyleConfig, StyleOverrides},
+    workspace::{self, DiscoveryConfig},
+};
+
+pub const CONFIG_FILE_NAME: &str = ".azp-analyzer.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub discovery: DiscoveryConfig,
+    pub style: StyleConfig,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<Override>,
+}
+
+/// Settings which apply to files matching `paths`, in place of the top-level settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Override {
+    /// Glob patterns, with the same syntax as [`DiscoveryConfig`], matched against the path of the file being checked
+    /// relative to the workspace root given to the [`Analyzer`](crate::Analyzer).
+    #[serde(deserialize_with = "deserialize_globs")]
+    pub paths: Vec<String>,
+    pub style: StyleOverrides,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: PathBuf, err: io::Error },
+    Parse { path: PathBuf, err: toml::de::Error },
+}
+
+impl Config {
+    /// Loads the configuration which applies to files in `dir`.
+    ///
+    /// `dir` is canonicalized first, so that relative paths such as `.` are searched all the way up to the
+    /// repository root.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Config, ConfigError> {
+        Config::load_with_home(dir.as_ref(), home_dir().as_deref())
+    }
+
+    fn load_with_home(dir: &Path, home: Option<&Path>) -> Result<Config, ConfigError> {
+        let dir = dir.canonicalize().map_err(|err| ConfigError::Io {
+            path: dir.to_owned(),
+            err,
+        })?;
+        Config::load_files(&config_files(&dir, home))
+    }
+
+    /// Loads and merges the given configuration files, in order of increasing precedence.
+    ///
+    /// Each file is validated on its own, so errors are reported against the file which caused them.
+    pub fn load_files(files: &[PathBuf]) -> Result<Config, ConfigError> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(?files, "loading config");
+        let mut merged = Table::new();
+        for path in files {
+            let text = fs::read_to_string(path).map_err(|err| ConfigError::Io {
+                path: path.clone(),
+                err,
+            })?;
+            let table = text.parse::<Table>().map_err(|err| ConfigError::Parse {
+                path: path.clone(),
+                err,
+            })?;
+            Config::deserialize(table.clone()).map_err(|err| ConfigError::Parse {
+                path: path.clone(),
+                err,
+            })?;
+            merge(&mut merged, table);
+        }
+
+        // Merging replaces values wholesale unless both are tables, so the result of merging valid files is valid.
+        Ok(Config::deserialize(merged).expect("merged config files should be valid"))
+    }
+
+    /// Formats the fully resolved configuration, including defaults, for debugging.
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string_pretty(self).expect("failed to serialize config")
+    }
+}
+
+/// Finds the configuration files which apply to `dir`, in order of increasing precedence.
+///
+/// Directories are searched upwards from `dir` until the root of the repository (a directory containing `.git`) is
+/// found. A configuration file in `home` is included first, if it exists and was not already found.
+pub fn config_files(dir: &Path, home: Option<&Path>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for ancestor in dir.ancestors() {
+        let path = ancestor.join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            files.push(path);
+        }
+        if ancestor.join(".git").exists() {
+            break;
+        }
+    }
+
+    if let Some(home) = home {
+        let path = home.join(CONFIG_FILE_NAME);
+        if path.is_file() && !files.contains(&path) {
+            files.push(path);
+        }
+    }
+
+    files.reverse();
+    files
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+fn deserialize_globs<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+    workspace::build_glob_set(&patterns).map_err(de::Error::custom)?;
+    Ok(patterns)
+}
+
+fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, .. } => {
+                write!(f, "failed to read config file '{}'", path.display())
+            }
+            ConfigError::Parse { path, .. } => {
+                write!(f, "invalid config file '{}'", path.display())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { err, .. } => Some(err),
+            ConfigError::Parse { err, .. } => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layered() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let home = dir.join("home");
+        let repo = dir.join("repo");
+        let sub = repo.join("deploy/prod");
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&sub).unwrap();
+
+        // Outside the repository, so ignored.
+        fs::write(dir.join(CONFIG_FILE_NAME), "invalid = ").unwrap();
+        fs::write(
+            home.join(CONFIG_FILE_NAME),
+            "[discovery]\npipelines = ['home.yml']\ntemplates = ['home/*.yml']\n",
+        )
+        .unwrap();
+        fs::write(
+            repo.join(CONFIG_FILE_NAME),
+            "[discovery]\npipelines = ['repo.yml']\n",
+        )
+        .unwrap();
+        fs::write(
+            repo.join("deploy").join(CONFIG_FILE_NAME),
+            "[discovery]\ntemplates = ['deploy/*.yml']\n",
+        )
+        .unwrap();
+
+        let files = config_files(&sub, Some(&home));
+        assert_eq!(
+            files,
+            [
+                home.join(CONFIG_FILE_NAME),
+                repo.join(CONFIG_FILE_NAME),
+                repo.join("deploy").join(CONFIG_FILE_NAME),
+            ]
+        );
+
+        let config = Config::load_files(&files).unwrap();
+        assert_eq!(config.discovery.pipelines, ["repo.yml"]);
+        assert_eq!(config.discovery.templates, ["deploy/*.yml"]);
+
+        let config = Config::load_files(&files[..1]).unwrap();
+        assert_eq!(config.discovery.pipelines, ["home.yml"]);
+        assert_eq!(config.discovery.templates, ["home/*.yml"]);
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(Config::load_files(&[]).unwrap(), Config::default());
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::write(
+            dir.join(CONFIG_FILE_NAME),
+            "[discovery]\npipelines = ['ci.yml']\n",
+        )
+        .unwrap();
+
+        let config = Config::load_files(&[dir.join(CONFIG_FILE_NAME)]).unwrap();
+        assert_eq!(config.discovery.pipelines, ["ci.yml"]);
+        assert_eq!(
+            config.discovery.templates,
+            DiscoveryConfig::default().templates
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let path = dir.join(CONFIG_FILE_NAME);
+
+        fs::write(&path, "unknown = 1").unwrap();
+        let err = Config::load_files(std::slice::from_ref(&path)).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        assert_eq!(
+            err.to_string(),
+            format!("invalid config file '{}'", path.display())
+        );
+
+        let missing = dir.join("missing.toml");
+        let err = Config::load_files(&[missing]).unwrap_err();
+        assert!(matches!(err, ConfigError::Io { .. }));
+
+        // Errors are attributed to the invalid file, not the last one.
+        let valid = dir.join("valid.toml");
+        fs::write(&valid, "[style]\nmax-line-length = 80\n").unwrap();
+        let err = Config::load_files(&[path.clone(), valid]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("invalid config file '{}'", path.display())
+        );
+    }
+
+    #[test]
+    fn load_relative() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            "[discovery]\npipelines = ['outer.yml']\n",
+        )
+        .unwrap();
+        fs::write(
+            nested.join(CONFIG_FILE_NAME),
+            "[discovery]\npipelines = ['nested.yml']\n",
+        )
+        .unwrap();
+
+        // A relative path to `nested`, which climbs from the working directory up to the root.
+        let cwd = env::current_dir().unwrap();
+        let nested = nested.canonicalize().unwrap();
+        let mut relative: PathBuf = cwd.ancestors().skip(1).map(|_| "..").collect();
+        relative.push(
+            nested
+                .strip_prefix(nested.ancestors().last().unwrap())
+                .unwrap(),
+        );
+
+        let config = Config::load_with_home(&relative, None).unwrap();
+        assert_eq!(config.discovery.pipelines, ["nested.yml"]);
+
+        // Lexically, the ancestors of `nested/..` include `nested`.
+        let config = Config::load_with_home(&relative.join(".."), None).unwrap();
+        assert_eq!(config.discovery.pipelines, ["outer.yml"]);
+
+        assert!(matches!(
+            Config::load_with_home(&relative.join("missing"), None),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+
+        fs::write(
+            &path,
+            "[[overrides]]\npaths = ['legacy/**']\nstyle.max-line-length = 240\n",
+        )
+        .unwrap();
+        let config = Config::load_files(std::slice::from_ref(&path)).unwrap();
+        assert_eq!(
+            config.overrides,
+            [Override {
+                paths: vec!["legacy/**".to_owned()],
+                style: StyleOverrides {
+                    max_line_length: Some(240),
+                    ..StyleOverrides::default()
+                },
+            }]
+        );
+        assert_eq!(
+            Config::deserialize(config.to_toml_string().parse::<Table>().unwrap()).unwrap(),
+            config
+        );
+
+        fs::write(&path, "[[overrides]]\npaths = ['legacy/[']\n").unwrap();
+        let err = Config::load_files(std::slice::from_ref(&path)).unwrap_err();
+        let ConfigError::Parse { err, .. } = err else {
+            panic!("expected parse error");
+        };
+        assert!(err.message().starts_with("invalid glob pattern"));
+    }
+
+    #[test]
+    fn print() {
+        let config = Config::default();
+        let text = config.to_toml_string();
+        assert!(text.contains("[discovery]"));
+        assert_eq!(text.parse::<Table>().unwrap().len(), 2);
+        assert_eq!(
+            Config::deserialize(text.parse::<Table>().unwrap()).unwrap(),
+            config
+        );
+    }
+}
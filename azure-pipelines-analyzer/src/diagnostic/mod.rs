@@ -1,15 +1,57 @@
+#[cfg(test)]
+mod tests;
+
+use std::{error::Error, fmt, str::FromStr};
+
 use serde::{Deserialize, Serialize};
 
-use crate::syntax::Span;
+use crate::{
+    syntax::{Encoding, ParseError, Span},
+    EditSet, FileId, InvalidEdits, LineEnding, TextEdit,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Diagnostic {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<FileId>,
     span: Span,
     severity: Severity,
     message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<Label>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fixes: Vec<Fix>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parse_error: Option<ParseError>,
+}
+
+/// A secondary location related to a diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Label {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<FileId>,
+    span: Span,
+    message: String,
+}
+
+/// A suggested change which resolves a diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fix {
+    message: String,
+    edits: Vec<TextEdit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<Encoding>,
+}
+
+/// Types which correspond to a span of source text.
+pub trait ToSpan {
+    fn to_span(&self) -> Span;
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+/// The severity of a diagnostic, ordered from least to most severe.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Severity {
     Hint,
     Information,
@@ -17,12 +59,277 @@ pub enum Severity {
     Error,
 }
 
+/// The error returned when parsing an unrecognized [`Severity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSeverityError {
+    value: String,
+}
+
 impl Diagnostic {
     pub fn new(span: Span, severity: Severity, message: impl ToString) -> Self {
         Diagnostic {
+            file: None,
             span,
             severity,
             message: message.to_string(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            fixes: Vec::new(),
+            parse_error: None,
+        }
+    }
+
+    pub(crate) fn from_parse_error(span: Span, error: ParseError) -> Self {
+        Diagnostic {
+            parse_error: Some(error.clone()),
+            ..Diagnostic::new(span, Severity::Error, error)
+        }
+    }
+
+    pub fn error(at: impl ToSpan, message: impl ToString) -> Self {
+        Diagnostic::new(at.to_span(), Severity::Error, message)
+    }
+
+    pub fn warning(at: impl ToSpan, message: impl ToString) -> Self {
+        Diagnostic::new(at.to_span(), Severity::Warning, message)
+    }
+
+    pub fn information(at: impl ToSpan, message: impl ToString) -> Self {
+        Diagnostic::new(at.to_span(), Severity::Information, message)
+    }
+
+    pub fn hint(at: impl ToSpan, message: impl ToString) -> Self {
+        Diagnostic::new(at.to_span(), Severity::Hint, message)
+    }
+
+    /// Sets the file this diagnostic belongs to. Labels without a file are assumed to be in the same file.
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = Some(file);
+        for label in &mut self.labels {
+            label.file.get_or_insert(file);
+        }
+        self
+    }
+
+    /// Adds a message pointing at a related location.
+    pub fn with_label(mut self, at: impl ToSpan, message: impl ToString) -> Self {
+        self.labels.push(Label {
+            file: self.file,
+            span: at.to_span(),
+            message: message.to_string(),
+        });
+        self
+    }
+
+    /// Adds a message pointing at a related location in another file.
+    pub fn with_label_in(mut self, file: FileId, at: impl ToSpan, message: impl ToString) -> Self {
+        self.labels.push(Label {
+            file: Some(file),
+            span: at.to_span(),
+            message: message.to_string(),
+        });
+        self
+    }
+
+    /// Adds a message with additional context which doesn't correspond to a location.
+    pub fn with_note(mut self, message: impl ToString) -> Self {
+        self.notes.push(message.to_string());
+        self
+    }
+
+    /// Adds a suggested fix, made up of one or more non-overlapping edits.
+    pub fn with_fix(
+        mut self,
+        message: impl ToString,
+        edits: impl IntoIterator<Item = TextEdit>,
+    ) -> Self {
+        self.fixes.push(Fix {
+            message: message.to_string(),
+            edits: edits.into_iter().collect(),
+            encoding: None,
+        });
+        self
+    }
+
+    /// Adds a suggested fix which also changes the encoding the file is written with.
+    pub fn with_encoding_fix(
+        mut self,
+        message: impl ToString,
+        edits: impl IntoIterator<Item = TextEdit>,
+        encoding: Encoding,
+    ) -> Self {
+        self.fixes.push(Fix {
+            message: message.to_string(),
+            edits: edits.into_iter().collect(),
+            encoding: Some(encoding),
+        });
+        self
+    }
+
+    /// Converts line breaks inserted by the fixes for this diagnostic to the given style, so that they match the
+    /// file they are applied to.
+    pub(crate) fn with_line_ending(mut self, ending: LineEnding) -> Self {
+        for fix in &mut self.fixes {
+            fix.edits = fix
+                .edits
+                .drain(..)
+                .map(|edit| edit.with_line_ending(ending))
+                .collect();
         }
+        self
+    }
+
+    /// The file this diagnostic belongs to, or `None` if it was produced from a standalone source text.
+    pub fn file(&self) -> Option<FileId> {
+        self.file
+    }
+
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    pub fn fixes(&self) -> &[Fix] {
+        &self.fixes
+    }
+
+    /// The structured cause of this diagnostic, if it is a syntax error reported by the parser.
+    pub fn parse_error(&self) -> Option<&ParseError> {
+        self.parse_error.as_ref()
     }
 }
+
+impl fmt::Debug for Diagnostic {
+    // Omits empty fields to keep parser snapshots concise.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("Diagnostic");
+        if let Some(file) = self.file {
+            s.field("file", &file);
+        }
+        s.field("span", &self.span)
+            .field("severity", &self.severity)
+            .field("message", &self.message);
+        if !self.labels.is_empty() {
+            s.field("labels", &self.labels);
+        }
+        if !self.notes.is_empty() {
+            s.field("notes", &self.notes);
+        }
+        if !self.fixes.is_empty() {
+            s.field("fixes", &self.fixes);
+        }
+        if let Some(parse_error) = &self.parse_error {
+            s.field("parse_error", parse_error);
+        }
+        s.finish()
+    }
+}
+
+impl Label {
+    pub fn file(&self) -> Option<FileId> {
+        self.file
+    }
+
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Fix {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn edits(&self) -> &[TextEdit] {
+        &self.edits
+    }
+
+    /// The encoding the file should be written with after applying this fix, if it changes it.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.encoding
+    }
+
+    /// Applies the fix to the decoded `text` of a file currently written with `encoding`, returning the new contents
+    /// of the file.
+    pub fn apply(&self, text: &str, encoding: Encoding) -> Result<Vec<u8>, InvalidEdits> {
+        let edits = EditSet::new(self.edits.iter().cloned())?;
+        Ok(self.encoding.unwrap_or(encoding).encode(&edits.apply(text)))
+    }
+}
+
+impl ToSpan for Span {
+    fn to_span(&self) -> Span {
+        self.clone()
+    }
+}
+
+impl<T: ToSpan + ?Sized> ToSpan for &T {
+    fn to_span(&self) -> Span {
+        (**self).to_span()
+    }
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Hint => "hint",
+            Severity::Information => "information",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = ParseSeverityError;
+
+    /// Parses a severity from its name, ignoring case. The abbreviations `info` and `warn` are also accepted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hint" => Ok(Severity::Hint),
+            "information" | "info" => Ok(Severity::Information),
+            "warning" | "warn" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(ParseSeverityError {
+                value: s.to_owned(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ParseSeverityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid severity '{}': expected one of 'hint', 'information', 'warning' or 'error'",
+            self.value
+        )
+    }
+}
+
+impl Error for ParseSeverityError {}
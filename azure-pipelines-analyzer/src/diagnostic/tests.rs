@@ -0,0 +1,114 @@
+use super::{Diagnostic, Severity};
+use crate::{LineEnding, SourceDatabase, TextEdit};
+
+#[test]
+fn severity_ordering() {
+    let mut severities = vec![
+        Severity::Error,
+        Severity::Hint,
+        Severity::Warning,
+        Severity::Information,
+    ];
+    severities.sort();
+    assert_eq!(
+        severities,
+        [
+            Severity::Hint,
+            Severity::Information,
+            Severity::Warning,
+            Severity::Error
+        ]
+    );
+    assert!(Severity::Warning >= Severity::Warning);
+    assert!(Severity::Error > Severity::Warning);
+}
+
+#[test]
+fn severity_display_round_trip() {
+    for severity in [
+        Severity::Hint,
+        Severity::Information,
+        Severity::Warning,
+        Severity::Error,
+    ] {
+        assert_eq!(severity.to_string().parse::<Severity>(), Ok(severity));
+    }
+}
+
+#[test]
+fn severity_from_str() {
+    assert_eq!("ERROR".parse::<Severity>(), Ok(Severity::Error));
+    assert_eq!("Warn".parse::<Severity>(), Ok(Severity::Warning));
+    assert_eq!("info".parse::<Severity>(), Ok(Severity::Information));
+    assert_eq!(
+        "fatal".parse::<Severity>().unwrap_err().to_string(),
+        "invalid severity 'fatal': expected one of 'hint', 'information', 'warning' or 'error'"
+    );
+}
+
+#[test]
+fn diagnostic_accessors() {
+    let diagnostic = Diagnostic::new(3..5, Severity::Warning, "message");
+    assert_eq!(diagnostic.span(), 3..5);
+    assert_eq!(diagnostic.severity(), Severity::Warning);
+    assert_eq!(diagnostic.message(), "message");
+}
+
+#[test]
+fn diagnostic_builder() {
+    let diagnostic = Diagnostic::warning(4..9, "unused anchor")
+        .with_label(&(0..3), "anchor defined here")
+        .with_note("anchors are not shared between template files")
+        .with_fix("remove the anchor", [TextEdit::delete(4..9)]);
+
+    assert_eq!(diagnostic.span(), 4..9);
+    assert_eq!(diagnostic.severity(), Severity::Warning);
+    assert_eq!(diagnostic.labels()[0].span(), 0..3);
+    assert_eq!(diagnostic.labels()[0].message(), "anchor defined here");
+    assert_eq!(
+        diagnostic.notes(),
+        ["anchors are not shared between template files"]
+    );
+    assert_eq!(diagnostic.fixes()[0].message(), "remove the anchor");
+    assert_eq!(diagnostic.fixes()[0].edits(), [TextEdit::delete(4..9)]);
+}
+
+#[test]
+fn diagnostic_debug_omits_empty_fields() {
+    assert_eq!(
+        format!("{:?}", Diagnostic::error(1..2, "message")),
+        r#"Diagnostic { span: 1..2, severity: Error, message: "message" }"#
+    );
+    assert_eq!(
+        format!("{:?}", Diagnostic::hint(1..2, "message").with_note("note")),
+        r#"Diagnostic { span: 1..2, severity: Hint, message: "message", notes: ["note"] }"#
+    );
+}
+
+#[test]
+fn diagnostic_file() {
+    let mut db = SourceDatabase::new();
+    let pipeline = db.set_file("azure-pipelines.yml", b"".as_slice());
+    let template = db.set_file("template.yml", b"".as_slice());
+
+    let diagnostic = Diagnostic::error(0..1, "missing parameter")
+        .with_label(2..3, "called here")
+        .with_label_in(template, 4..5, "parameter declared here")
+        .with_file(pipeline);
+    assert_eq!(diagnostic.file(), Some(pipeline));
+    assert_eq!(diagnostic.labels()[0].file(), Some(pipeline));
+    assert_eq!(diagnostic.labels()[1].file(), Some(template));
+}
+
+#[test]
+fn diagnostic_line_ending() {
+    let diagnostic = Diagnostic::warning(0..0, "message")
+        .with_fix(
+            "fix",
+            [TextEdit::insert(0, "a\nb\r\n"), TextEdit::delete(1..2)],
+        )
+        .with_line_ending(LineEnding::CrLf);
+    let edits = diagnostic.fixes()[0].edits();
+    assert_eq!(edits[0].text(), "a\r\nb\r\n");
+    assert_eq!(edits[1].text(), "");
+}
@@ -0,0 +1,415 @@
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::syntax::Span;
+
+/// A replacement of a span of source text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TextEdit {
+    span: Span,
+    text: String,
+}
+
+/// A set of non-overlapping edits to a single file, which can be applied together.
+///
+/// Edits are kept sorted by position, and adjacent edits are merged. Insertions at the same offset are applied in the
+/// order they were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditSet {
+    edits: Vec<TextEdit>,
+}
+
+/// A style of line break.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+/// The reason edits could not be combined into an [`EditSet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidEdits {
+    Overlapping(OverlappingEdits),
+    /// An edit whose span ends before it starts.
+    ReversedSpan(TextEdit),
+}
+
+/// Two edits in an [`EditSet`] which modify the same text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlappingEdits {
+    pub first: TextEdit,
+    pub second: TextEdit,
+}
+
+impl TextEdit {
+    pub fn replace(span: Span, text: impl Into<String>) -> Self {
+        TextEdit {
+            span,
+            text: text.into(),
+        }
+    }
+
+    pub fn insert(offset: usize, text: impl Into<String>) -> Self {
+        TextEdit::replace(offset..offset, text)
+    }
+
+    pub fn delete(span: Span) -> Self {
+        TextEdit::replace(span, String::new())
+    }
+
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn is_empty(&self) -> bool {
+        self.span.is_empty() && self.text.is_empty()
+    }
+
+    pub(crate) fn with_line_ending(mut self, ending: LineEnding) -> Self {
+        self.text = ending.convert(&self.text);
+        self
+    }
+
+    /// Shrinks the edit to exclude any prefix or suffix of the replaced text which it leaves unchanged.
+    fn minimize(&mut self, original: &str) {
+        let old = &original[self.span.clone()];
+
+        let prefix = common_prefix_len(old.chars(), self.text.chars());
+        let suffix = common_prefix_len(
+            old[prefix..].chars().rev(),
+            self.text[prefix..].chars().rev(),
+        );
+
+        self.span = (self.span.start + prefix)..(self.span.end - suffix);
+        self.text = self.text[prefix..(self.text.len() - suffix)].to_owned();
+    }
+}
+
+impl EditSet {
+    /// Creates a set of edits, returning an error if any two edits overlap or any span ends before it starts.
+    ///
+    /// Edits which touch without overlapping, such as an insertion at the end of a replaced span, are allowed.
+    pub fn new(edits: impl IntoIterator<Item = TextEdit>) -> Result<Self, InvalidEdits> {
+        let mut edits: Vec<TextEdit> = edits.into_iter().collect();
+        if let Some(edit) = edits.iter().find(|edit| edit.span.start > edit.span.end) {
+            return Err(InvalidEdits::ReversedSpan(edit.clone()));
+        }
+        edits.sort_by_key(|edit| (edit.span.start, edit.span.end));
+
+        let mut merged: Vec<TextEdit> = Vec::with_capacity(edits.len());
+        for edit in edits {
+            match merged.last_mut() {
+                Some(last) if edit.span.start < last.span.end => {
+                    return Err(InvalidEdits::Overlapping(OverlappingEdits {
+                        first: last.clone(),
+                        second: edit,
+                    }));
+                }
+                Some(last) if edit.span.start == last.span.end => {
+                    last.span.end = edit.span.end;
+                    last.text.push_str(&edit.text);
+                }
+                _ => merged.push(edit),
+            }
+        }
+
+        merged.retain(|edit| !edit.is_empty());
+        Ok(EditSet { edits: merged })
+    }
+
+    /// Gets the edits in this set, sorted by position.
+    pub fn edits(&self) -> &[TextEdit] {
+        &self.edits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Shrinks each edit to only the text it changes, and removes any edits which have no effect on `original`.
+    ///
+    /// For example, replacing `foo: bar` with `foo: baz` becomes a replacement of `r` with `z`.
+    pub fn minimize(&mut self, original: &str) {
+        for edit in &mut self.edits {
+            edit.minimize(original);
+        }
+        self.edits.retain(|edit| !edit.is_empty());
+    }
+
+    /// Converts line breaks in the inserted text to the given style, so that edits preserve the line endings of the
+    /// file they are applied to (see [`LineEnding::detect`]).
+    pub fn with_line_ending(mut self, ending: LineEnding) -> Self {
+        self.edits = self
+            .edits
+            .into_iter()
+            .map(|edit| edit.with_line_ending(ending))
+            .collect();
+        self
+    }
+
+    /// Applies the edits to `text`, which must contain all of the edited spans.
+    pub fn apply(&self, text: &str) -> String {
+        let len = self
+            .edits
+            .iter()
+            .fold(text.len() as isize, |len, edit| len + delta(edit));
+        let mut result = String::with_capacity(len as usize);
+
+        let mut pos = 0;
+        for edit in &self.edits {
+            result.push_str(&text[pos..edit.span.start]);
+            result.push_str(&edit.text);
+            pos = edit.span.end;
+        }
+        result.push_str(&text[pos..]);
+        result
+    }
+
+    /// Maps an offset in the original text to the corresponding offset in the edited text.
+    ///
+    /// Offsets at the end of an edit are moved past the inserted text. Returns `None` if the offset is strictly inside a
+    /// replaced span, since it has no corresponding position after the edit.
+    pub fn map_offset(&self, offset: usize) -> Option<usize> {
+        let mut shift = 0;
+        for edit in &self.edits {
+            if offset < edit.span.start || (offset == edit.span.start && !edit.span.is_empty()) {
+                break;
+            } else if offset < edit.span.end {
+                return None;
+            }
+            shift += delta(edit);
+        }
+        Some((offset as isize + shift) as usize)
+    }
+
+    /// Maps a span in the original text to the corresponding span in the edited text.
+    ///
+    /// Returns `None` if either end of the span is strictly inside a replaced span.
+    pub fn map_span(&self, span: Span) -> Option<Span> {
+        Some(self.map_offset(span.start)?..self.map_offset(span.end)?)
+    }
+}
+
+impl LineEnding {
+    /// Finds the most common line ending in `text`, or `None` if it contains no line breaks.
+    pub fn detect(text: &str) -> Option<LineEnding> {
+        let lines = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        if lines == 0 {
+            None
+        } else if crlf > lines - crlf {
+            Some(LineEnding::CrLf)
+        } else {
+            Some(LineEnding::Lf)
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// Creates edits which convert all line breaks in `text` to this style.
+    pub fn normalize(self, text: &str) -> EditSet {
+        let edits = text.match_indices('\n').filter_map(|(pos, _)| {
+            let crlf = text[..pos].ends_with('\r');
+            match self {
+                LineEnding::Lf if crlf => Some(TextEdit::delete((pos - 1)..pos)),
+                LineEnding::CrLf if !crlf => Some(TextEdit::insert(pos, "\r")),
+                _ => None,
+            }
+        });
+        EditSet {
+            edits: edits.collect(),
+        }
+    }
+
+    fn convert(self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n");
+        match self {
+            LineEnding::Lf => text,
+            LineEnding::CrLf => text.replace('\n', "\r\n"),
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineEnding::Lf => f.write_str("LF"),
+            LineEnding::CrLf => f.write_str("CRLF"),
+        }
+    }
+}
+
+fn delta(edit: &TextEdit) -> isize {
+    edit.text.len() as isize - edit.span.len() as isize
+}
+
+fn common_prefix_len(l: impl Iterator<Item = char>, r: impl Iterator<Item = char>) -> usize {
+    l.zip(r)
+        .take_while(|(l, r)| l == r)
+        .map(|(ch, _)| ch.len_utf8())
+        .sum()
+}
+
+impl fmt::Display for OverlappingEdits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "edit at {:?} overlaps edit at {:?}",
+            self.second.span, self.first.span
+        )
+    }
+}
+
+impl Error for OverlappingEdits {}
+
+impl fmt::Display for InvalidEdits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidEdits::Overlapping(err) => err.fmt(f),
+            InvalidEdits::ReversedSpan(edit) => {
+                write!(f, "edit at {:?} ends before it starts", edit.span)
+            }
+        }
+    }
+}
+
+impl Error for InvalidEdits {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InvalidEdits::Overlapping(err) => Some(err),
+            InvalidEdits::ReversedSpan(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply() {
+        let edits = EditSet::new([
+            TextEdit::replace(6..9, "baz"),
+            TextEdit::insert(0, "- "),
+            TextEdit::delete(9..13),
+        ])
+        .unwrap();
+        assert_eq!(
+            edits.edits(),
+            [TextEdit::insert(0, "- "), TextEdit::replace(6..13, "baz")]
+        );
+        assert_eq!(edits.apply("name: bar # x\n"), "- name: baz\n");
+    }
+
+    #[test]
+    fn insertions_keep_order() {
+        let edits = EditSet::new([
+            TextEdit::insert(3, "a"),
+            TextEdit::replace(3..4, "c"),
+            TextEdit::insert(3, "b"),
+        ])
+        .unwrap();
+        assert_eq!(edits.edits(), [TextEdit::replace(3..4, "abc")]);
+        assert_eq!(edits.apply("foo:"), "fooabc");
+    }
+
+    #[test]
+    fn overlapping() {
+        let Err(InvalidEdits::Overlapping(err)) =
+            EditSet::new([TextEdit::delete(2..5), TextEdit::insert(3, "x")])
+        else {
+            panic!("expected overlapping edits");
+        };
+        assert_eq!(err.first, TextEdit::delete(2..5));
+        assert_eq!(err.second, TextEdit::insert(3, "x"));
+        assert_eq!(err.to_string(), "edit at 3..3 overlaps edit at 2..5");
+
+        assert!(EditSet::new([TextEdit::delete(2..5), TextEdit::delete(4..6)]).is_err());
+        assert!(EditSet::new([TextEdit::delete(2..5), TextEdit::insert(5, "x")]).is_ok());
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn reversed_span() {
+        let err =
+            EditSet::new([TextEdit::insert(0, "x"), TextEdit::replace(5..3, "")]).unwrap_err();
+        assert_eq!(err, InvalidEdits::ReversedSpan(TextEdit::delete(5..3)));
+        assert_eq!(err.to_string(), "edit at 5..3 ends before it starts");
+    }
+
+    #[test]
+    fn minimize() {
+        let text = "pool: ubuntu-20.04\nsteps: []\n";
+        let mut edits = EditSet::new([
+            TextEdit::replace(0..18, "pool: ubuntu-22.04"),
+            TextEdit::replace(19..28, "steps: []"),
+        ])
+        .unwrap();
+        edits.minimize(text);
+        assert_eq!(edits.edits(), [TextEdit::replace(14..15, "2")]);
+        assert_eq!(edits.apply(text), "pool: ubuntu-22.04\nsteps: []\n");
+
+        let mut edits = EditSet::new([TextEdit::replace(0..2, "äb")]).unwrap();
+        edits.minimize("ab");
+        assert_eq!(edits.edits(), [TextEdit::replace(0..1, "ä")]);
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(LineEnding::detect("a"), None);
+        assert_eq!(LineEnding::detect("a\nb\r\nc\n"), Some(LineEnding::Lf));
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), Some(LineEnding::CrLf));
+
+        let text = "steps:\r\n- script: a\r\n";
+        let ending = LineEnding::detect(text).unwrap();
+        let edits = EditSet::new([TextEdit::insert(
+            text.len(),
+            "- script: b\n  displayName: b\n",
+        )])
+        .unwrap()
+        .with_line_ending(ending);
+        assert_eq!(
+            edits.apply(text),
+            "steps:\r\n- script: a\r\n- script: b\r\n  displayName: b\r\n"
+        );
+
+        let text = "a\r\nb\nc\r\n";
+        assert_eq!(LineEnding::Lf.normalize(text).apply(text), "a\nb\nc\n");
+        assert_eq!(
+            LineEnding::CrLf.normalize(text).apply(text),
+            "a\r\nb\r\nc\r\n"
+        );
+        assert!(LineEnding::CrLf.normalize("a\r\n").is_empty());
+    }
+
+    #[test]
+    fn map_offset() {
+        let edits =
+            EditSet::new([TextEdit::insert(2, "xyz"), TextEdit::replace(4..8, "a")]).unwrap();
+
+        assert_eq!(edits.map_offset(0), Some(0));
+        assert_eq!(edits.map_offset(2), Some(5));
+        assert_eq!(edits.map_offset(4), Some(7));
+        assert_eq!(edits.map_offset(5), None);
+        assert_eq!(edits.map_offset(8), Some(8));
+        assert_eq!(edits.map_offset(10), Some(10));
+        assert_eq!(edits.map_span(0..10), Some(0..10));
+        assert_eq!(edits.map_span(3..6), None);
+
+        let text = "0123456789";
+        let edited = edits.apply(text);
+        assert_eq!(edited, "01xyz23a89");
+        assert_eq!(&edited[edits.map_offset(8).unwrap()..], &text[8..]);
+    }
+}
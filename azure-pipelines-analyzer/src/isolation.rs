@@ -0,0 +1,120 @@
+//! Catching panics while analyzing a file, so that a bug triggered by one file does not abort the analysis of others.
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+};
+
+use crate::Diagnostic;
+
+/// A panic caught by [`isolate`].
+#[derive(Debug)]
+pub(crate) struct Panic {
+    message: String,
+    location: Option<String>,
+}
+
+thread_local! {
+    static ISOLATED: Cell<bool> = const { Cell::new(false) };
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, catching any panic.
+///
+/// Caught panics are reported as diagnostics, so the default panic hook printing them to stderr as well would only add
+/// noise to the analyzer's output. The first call installs a panic hook which records the location of panics inside
+/// `isolate` instead of printing them, and forwards all other panics to the previous hook.
+pub(crate) fn isolate<T>(f: impl FnOnce() -> T) -> Result<T, Panic> {
+    static INSTALL_HOOK: Once = Once::new();
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if ISOLATED.with(Cell::get) {
+                let location = info.location().map(ToString::to_string);
+                LOCATION.with(|cell| *cell.borrow_mut() = location);
+            } else {
+                previous(info)
+            }
+        }));
+    });
+
+    let was_isolated = ISOLATED.with(|isolated| isolated.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    ISOLATED.with(|isolated| isolated.set(was_isolated));
+
+    result.map_err(|payload| Panic {
+        message: panic_message(&*payload).to_owned(),
+        location: LOCATION.with(RefCell::take),
+    })
+}
+
+impl Panic {
+    /// Reports the panic as an internal error for the file being analyzed, where `action` describes what the analyzer
+    /// was doing, such as "parsing".
+    pub(crate) fn into_diagnostic(self, action: &str) -> Diagnostic {
+        #[cfg(feature = "tracing")]
+        tracing::error!(
+            message = self.message,
+            location = self.location,
+            "analyzer panicked while {action}"
+        );
+
+        let mut diagnostic = Diagnostic::error(
+            0..0,
+            format!(
+                "internal error: the analyzer panicked while {action} this file: {}",
+                self.message
+            ),
+        );
+        if let Some(location) = self.location {
+            diagnostic = diagnostic.with_note(format!("panicked at {location}"));
+        }
+        diagnostic.with_note(
+            "this is a bug in azure-pipelines-analyzer, please report it along with this file",
+        )
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isolate_panic() {
+        assert_eq!(isolate(|| 1).unwrap(), 1);
+
+        let line = line!() + 1;
+        let panic = isolate(|| panic!("failed with {}", 1)).unwrap_err();
+        assert_eq!(panic.message, "failed with 1");
+        assert_eq!(panic.location, Some(format!("{}:{line}:32", file!())));
+
+        let diagnostic = panic.into_diagnostic("parsing");
+        assert_eq!(
+            diagnostic.message(),
+            "internal error: the analyzer panicked while parsing this file: failed with 1"
+        );
+        assert_eq!(diagnostic.notes().len(), 2);
+    }
+
+    #[test]
+    fn isolate_nested() {
+        let result = isolate(|| {
+            assert!(isolate(|| panic!("inner")).is_err());
+            assert!(ISOLATED.with(Cell::get));
+        });
+        assert!(result.is_ok());
+        assert!(!ISOLATED.with(Cell::get));
+    }
+}
@@ -1,4 +1,20 @@
+mod analyzer;
+mod cancellation;
+pub mod config;
 mod diagnostic;
+mod edit;
+mod isolation;
+mod observer;
+mod source;
+pub mod style;
 pub mod syntax;
+pub mod workspace;
 
-pub use self::diagnostic::Diagnostic;
+pub use self::{
+    analyzer::{Analyzer, Capabilities, Report},
+    cancellation::{CancellationToken, Cancelled},
+    diagnostic::{Diagnostic, Fix, Label, ParseSeverityError, Severity, ToSpan},
+    edit::{EditSet, InvalidEdits, LineEnding, OverlappingEdits, TextEdit},
+    observer::{AnalysisObserver, Phase, PhaseEvent},
+    source::{FileId, SourceDatabase},
+};
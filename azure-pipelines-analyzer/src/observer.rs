@@ -0,0 +1,51 @@
+use std::{fmt, time::Duration};
+
+use crate::FileId;
+
+/// Receives timing and size information as files are analyzed, for exporting metrics from hosted deployments.
+///
+/// Observers are called synchronously on the analysis thread, so implementations should be cheap, for example by
+/// recording into atomic counters or a metrics registry.
+pub trait AnalysisObserver: Send + Sync {
+    fn phase_finished(&self, event: &PhaseEvent);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Detecting the encoding of a file and decoding it to UTF-8.
+    Decode,
+    /// Building the syntax tree.
+    Parse,
+    /// Running the style checks configured for the file.
+    Lint,
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PhaseEvent {
+    /// The file being analyzed, if it belongs to a [`SourceDatabase`](crate::SourceDatabase).
+    pub file: Option<FileId>,
+    pub phase: Phase,
+    pub elapsed: Duration,
+    /// The size of the input to this phase, in bytes.
+    pub input_len: usize,
+    /// The number of diagnostics produced by this phase.
+    pub diagnostics: usize,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Decode => "decode",
+            Phase::Parse => "parse",
+            Phase::Lint => "lint",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
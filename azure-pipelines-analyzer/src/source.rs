@@ -0,0 +1,426 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    syntax::{self, Decoded, Encoding, Parse, ParseCache, ParseOptions},
+    AnalysisObserver,
+};
+
+/// Identifies a file in a [`SourceDatabase`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct FileId(u32);
+
+/// Maps file ids to their paths and contents, so that analyses spanning multiple files can report which file a
+/// diagnostic belongs to.
+///
+/// Files can be loaded from disk, or from an overlay of in-memory contents which shadow the file on disk, such as
+/// unsaved editor buffers.
+///
+/// Paths are normalized lexically before they are interned, so `./a.yml` and `a.yml` refer to the same file.
+/// Symbolic links are not resolved, since overlaid files need not exist on disk.
+#[derive(Default)]
+pub struct SourceDatabase {
+    files: Vec<SourceFile>,
+    ids: HashMap<PathBuf, FileId>,
+    /// Overlaid paths, along with any contents set by [`set_file`](Self::set_file) while the overlay was active,
+    /// which replace it when the overlay is removed.
+    overlays: HashMap<PathBuf, Option<Arc<[u8]>>>,
+    cache: ParseCache,
+    observer: Option<Arc<dyn AnalysisObserver>>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,
+    contents: Arc<[u8]>,
+    revision: u64,
+}
+
+impl SourceDatabase {
+    pub fn new() -> Self {
+        SourceDatabase::default()
+    }
+
+    /// Sets the contents of the file at `path`, returning its id.
+    ///
+    /// If the path was already added, its contents are replaced and the existing id is returned. If the path has an
+    /// overlay, the overlay keeps shadowing `contents` until it is removed.
+    pub fn set_file(&mut self, path: impl Into<PathBuf>, contents: impl Into<Arc<[u8]>>) -> FileId {
+        let path = normalize(&path.into());
+        match self.overlays.get_mut(&path) {
+            Some(shadowed) => {
+                *shadowed = Some(contents.into());
+                self.ids[&path]
+            }
+            None => self.set_contents(path, contents.into()),
+        }
+    }
+
+    /// Loads the file at `path`, preferring overlay contents over the file on disk.
+    pub fn load(&mut self, path: impl Into<PathBuf>) -> io::Result<FileId> {
+        let path = normalize(&path.into());
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = %path.display(), overlay = self.has_overlay(&path), "loading file");
+        if self.overlays.contains_key(&path) {
+            return Ok(self.ids[&path]);
+        }
+        let contents = fs::read(&path)?;
+        Ok(self.set_contents(path, contents.into()))
+    }
+
+    /// Shadows the file at `path` with in-memory contents until [`remove_overlay`](Self::remove_overlay) is called.
+    pub fn set_overlay(
+        &mut self,
+        path: impl Into<PathBuf>,
+        contents: impl Into<Arc<[u8]>>,
+    ) -> FileId {
+        let path = normalize(&path.into());
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = %path.display(), "setting overlay");
+        self.overlays.entry(path.clone()).or_default();
+        self.set_contents(path, contents.into())
+    }
+
+    /// Removes the overlay for `path`, restoring the contents last passed to [`set_file`](Self::set_file) while it
+    /// was active, or otherwise reloading the file from disk.
+    ///
+    /// Returns `Ok(None)` if there was no overlay. If the file does not exist on disk, for example because an
+    /// editor buffer was never saved, the overlay is removed but its last contents are kept.
+    pub fn remove_overlay(&mut self, path: impl AsRef<Path>) -> io::Result<Option<FileId>> {
+        let path = normalize(path.as_ref());
+        let shadowed = match self.overlays.remove(&path) {
+            Some(shadowed) => shadowed,
+            None => return Ok(None),
+        };
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = %path.display(), "removed overlay");
+
+        let contents = match shadowed {
+            Some(contents) => contents,
+            None => match fs::read(&path) {
+                Ok(contents) => contents.into(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(self.file_id(&path)),
+                Err(err) => return Err(err),
+            },
+        };
+        Ok(Some(self.set_contents(path, contents)))
+    }
+
+    pub fn has_overlay(&self, path: impl AsRef<Path>) -> bool {
+        self.overlays.contains_key(&normalize(path.as_ref()))
+    }
+
+    pub fn file_id(&self, path: impl AsRef<Path>) -> Option<FileId> {
+        self.ids.get(&normalize(path.as_ref())).copied()
+    }
+
+    pub fn path(&self, id: FileId) -> &Path {
+        &self.files[id.index()].path
+    }
+
+    pub fn contents(&self, id: FileId) -> Arc<[u8]> {
+        self.files[id.index()].contents.clone()
+    }
+
+    /// Gets the detected encoding of a file, which should be used when writing changes back to it.
+    pub fn encoding(&self, id: FileId) -> Encoding {
+        Encoding::detect(&self.files[id.index()].contents)
+    }
+
+    /// A counter which is incremented whenever the contents of the file change, for invalidating cached results.
+    pub fn revision(&self, id: FileId) -> u64 {
+        self.files[id.index()].revision
+    }
+
+    pub fn files(&self) -> impl ExactSizeIterator<Item = FileId> {
+        (0..self.files.len() as u32).map(FileId)
+    }
+
+    /// Sets an observer which is notified as files are analyzed.
+    pub fn set_observer(&mut self, observer: Arc<dyn AnalysisObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub(crate) fn observer(&self) -> Option<&dyn AnalysisObserver> {
+        self.observer.as_deref()
+    }
+
+    /// Parses a file, attributing its diagnostics to `id`.
+    ///
+    /// If the parser panics, the panic is caught and reported as an internal error diagnostic for this file, so that
+    /// other files can still be analyzed.
+    pub fn parse(&mut self, id: FileId) -> Parse {
+        let contents = self.contents(id);
+        let decoded = self.decode(id, &contents);
+        self.parse_decoded(id, &decoded)
+    }
+
+    /// Decodes the contents of a file, so that the decoded text can be shared by the parser and other checks.
+    pub(crate) fn decode<'a>(&self, id: FileId, contents: &'a [u8]) -> Decoded<'a> {
+        let options = ParseOptions {
+            file: Some(id),
+            observer: self.observer.as_deref(),
+            ..Default::default()
+        };
+        syntax::decode_with_options(contents, &options)
+    }
+
+    /// Parses a file which was already decoded by [`decode`](Self::decode).
+    pub(crate) fn parse_decoded(&mut self, id: FileId, decoded: &Decoded) -> Parse {
+        let options = ParseOptions {
+            file: Some(id),
+            observer: self.observer.as_deref(),
+            ..Default::default()
+        };
+        syntax::parse_isolated(decoded, &mut self.cache, &options)
+            .expect("parsing without a cancellation token cannot be cancelled")
+    }
+
+    fn set_contents(&mut self, path: PathBuf, contents: Arc<[u8]>) -> FileId {
+        match self.ids.get(&path) {
+            Some(&id) => {
+                let file = &mut self.files[id.index()];
+                if file.contents != contents {
+                    file.contents = contents;
+                    file.revision += 1;
+                }
+                id
+            }
+            None => {
+                let id = FileId(self.files.len().try_into().expect("too many files"));
+                self.ids.insert(path.clone(), id);
+                self.files.push(SourceFile {
+                    path,
+                    contents,
+                    revision: 0,
+                });
+                id
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SourceDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceDatabase")
+            .field("files", &self.files)
+            .field("overlays", &self.overlays.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Removes `.` components, and `..` components which follow a normal component or the root, without touching the
+/// file system.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match (component, normalized.components().next_back()) {
+            (Component::CurDir, _) | (Component::ParentDir, Some(Component::RootDir)) => {}
+            (Component::ParentDir, Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            (component, _) => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{EditSet, Phase, PhaseEvent, TextEdit};
+
+    #[test]
+    fn set_file() {
+        let mut db = SourceDatabase::new();
+        let a = db.set_file("a.yml", b"%YAML 1.2".as_slice());
+        let b = db.set_file("b.yml", b"%".as_slice());
+        assert_ne!(a, b);
+        assert_eq!(db.file_id("a.yml"), Some(a));
+        assert_eq!(db.file_id("c.yml"), None);
+        assert_eq!(db.path(b), Path::new("b.yml"));
+
+        assert_eq!(db.set_file("a.yml", b"%TAG ! !".as_slice()), a);
+        assert_eq!(&*db.contents(a), b"%TAG ! !");
+        assert_eq!(db.files().collect::<Vec<_>>(), [a, b]);
+    }
+
+    #[test]
+    fn normalize_paths() {
+        let mut db = SourceDatabase::new();
+        let id = db.set_file("./pipelines/a.yml", b"%YAML 1.2".as_slice());
+        assert_eq!(
+            db.set_file("pipelines/./a.yml", b"%YAML 1.2".as_slice()),
+            id
+        );
+        assert_eq!(db.file_id("templates/../pipelines/a.yml"), Some(id));
+        assert_eq!(db.path(id), Path::new("pipelines/a.yml"));
+
+        assert_eq!(
+            db.set_overlay("./pipelines/a.yml", b"%YAML 1.1".as_slice()),
+            id
+        );
+        assert!(db.has_overlay("pipelines/a.yml"));
+
+        assert_eq!(normalize(Path::new("../a/./b/../c")), Path::new("../a/c"));
+        assert_eq!(normalize(Path::new("/a/../../b")), Path::new("/b"));
+    }
+
+    #[test]
+    fn set_file_with_overlay() {
+        let mut db = SourceDatabase::new();
+        let id = db.set_file("a.yml", b"%YAML 1.2".as_slice());
+        db.set_overlay("a.yml", b"%YAML 1.1".as_slice());
+
+        // The overlay keeps shadowing updates to the underlying file...
+        assert_eq!(db.set_file("a.yml", b"%TAG ! !".as_slice()), id);
+        assert_eq!(&*db.contents(id), b"%YAML 1.1");
+        assert_eq!(db.revision(id), 1);
+
+        // ...which take effect once it is removed.
+        assert_eq!(db.remove_overlay("a.yml").unwrap(), Some(id));
+        assert_eq!(&*db.contents(id), b"%TAG ! !");
+        assert_eq!(db.revision(id), 2);
+    }
+
+    #[test]
+    fn overlay() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let path = dir.join("azure-pipelines.yml");
+        fs::write(&path, "%YAML 1.2").unwrap();
+
+        let mut db = SourceDatabase::new();
+        let id = db.load(&path).unwrap();
+        assert_eq!(&*db.contents(id), b"%YAML 1.2");
+        assert_eq!(db.revision(id), 0);
+
+        assert_eq!(db.set_overlay(&path, b"%YAML 1.1".as_slice()), id);
+        assert!(db.has_overlay(&path));
+        assert_eq!(&*db.contents(id), b"%YAML 1.1");
+        assert_eq!(db.revision(id), 1);
+
+        // Overlays take precedence over the file on disk.
+        assert_eq!(db.load(&path).unwrap(), id);
+        assert_eq!(&*db.contents(id), b"%YAML 1.1");
+        assert_eq!(db.revision(id), 1);
+
+        assert_eq!(db.remove_overlay(&path).unwrap(), Some(id));
+        assert!(!db.has_overlay(&path));
+        assert_eq!(&*db.contents(id), b"%YAML 1.2");
+        assert_eq!(db.revision(id), 2);
+        assert_eq!(db.remove_overlay(&path).unwrap(), None);
+
+        // Unsaved files only exist as overlays.
+        let unsaved = dir.join("unsaved.yml");
+        let unsaved_id = db.set_overlay(&unsaved, b"%TAG ! !".as_slice());
+        assert!(db.load(dir.join("missing.yml")).is_err());
+        assert_eq!(db.load(&unsaved).unwrap(), unsaved_id);
+        assert_eq!(db.remove_overlay(&unsaved).unwrap(), Some(unsaved_id));
+        assert_eq!(&*db.contents(unsaved_id), b"%TAG ! !");
+    }
+
+    #[test]
+    fn parse_attributes_diagnostics() {
+        let mut db = SourceDatabase::new();
+        let id = db.set_file("azure-pipelines.yml", b"%".as_slice());
+        let parse = db.parse(id);
+        assert!(!parse.errors().is_empty());
+        assert!(parse.errors().iter().all(|error| error.file() == Some(id)));
+    }
+
+    #[test]
+    fn observer() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<PhaseEvent>>);
+
+        impl AnalysisObserver for Recorder {
+            fn phase_finished(&self, event: &PhaseEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let mut db = SourceDatabase::new();
+        db.set_observer(recorder.clone());
+
+        let valid = db.set_file("valid.yml", b"%YAML 1.2 #".as_slice());
+        let invalid = db.set_file("invalid.yml", b"%YAML \xff".as_slice());
+        db.parse(valid);
+        db.parse(invalid);
+
+        let events = recorder.0.lock().unwrap();
+        let summary: Vec<_> = events
+            .iter()
+            .map(|event| (event.file, event.phase, event.input_len, event.diagnostics))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Some(valid), Phase::Decode, 11, 0),
+                (Some(valid), Phase::Parse, 11, 0),
+                (Some(invalid), Phase::Decode, 7, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_panic() {
+        struct Panicker;
+
+        impl AnalysisObserver for Panicker {
+            fn phase_finished(&self, event: &PhaseEvent) {
+                if event.phase == Phase::Parse {
+                    panic!("observer failed");
+                }
+            }
+        }
+
+        let mut db = SourceDatabase::new();
+        db.set_observer(Arc::new(Panicker));
+        let invalid = db.set_file("invalid.yml", b"\xff".as_slice());
+        let valid = db.set_file("valid.yml", b"%YAML 1.2".as_slice());
+
+        let parse = db.parse(valid);
+        assert_eq!(parse.errors().len(), 1);
+        assert_eq!(parse.errors()[0].file(), Some(valid));
+        assert_eq!(
+            parse.errors()[0].message(),
+            "internal error: the analyzer panicked while parsing this file: observer failed"
+        );
+        assert_eq!(parse.errors()[0].notes().len(), 2);
+
+        // Other files are unaffected.
+        let parse = db.parse(invalid);
+        assert_eq!(
+            parse.errors()[0].message(),
+            "source file was not valid utf-8: invalid byte sequence"
+        );
+    }
+
+    #[test]
+    fn encoding() {
+        let mut db = SourceDatabase::new();
+        let contents = Encoding::Utf16Le.encode("\u{feff}%YAML 1.2");
+        let id = db.set_file("utf16.yml", contents);
+        assert_eq!(db.encoding(id), Encoding::Utf16Le);
+
+        let edits = EditSet::new([TextEdit::replace(9..12, "1.1")]).unwrap();
+        let edited = db.encoding(id).encode(&edits.apply("\u{feff}%YAML 1.2"));
+        assert_eq!(edited, Encoding::Utf16Le.encode("\u{feff}%YAML 1.1"));
+    }
+}
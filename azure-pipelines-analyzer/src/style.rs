@@ -0,0 +1,414 @@
+//! Configurable style rules which apply to the text of a file.
+//!
+//! Limits can be relaxed for part of a repository with an `[[overrides]]` section matching its paths (see
+//! [`config`](crate::config)).
+
+use std::iter;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{syntax::Encoding, Diagnostic, LineEnding, TextEdit};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StyleConfig {
+    /// The maximum number of characters in a line, or `0` for no limit.
+    pub max_line_length: usize,
+    /// The maximum number of lines in a file, or `0` for no limit.
+    pub max_file_lines: usize,
+    /// The line ending files should use. If unset, files may use either, and fixes preserve the existing style.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_ending: Option<LineEnding>,
+    /// Whether to warn about files which are not encoded as UTF-8.
+    pub require_utf8: bool,
+    /// Whether to warn about non-empty files which do not end with a line break.
+    pub trailing_newline: bool,
+}
+
+/// Style settings for files matching an [`Override`](crate::config::Override). Unset fields keep their value from
+/// the top-level [`StyleConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StyleOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_line_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_file_lines: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_ending: Option<LineEnding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_utf8: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailing_newline: Option<bool>,
+}
+
+impl Default for StyleConfig {
+    fn default() -> Self {
+        // Inline scripts and task inputs often contain long lines, so the default line limit is fairly lenient.
+        StyleConfig {
+            max_line_length: 160,
+            max_file_lines: 1000,
+            line_ending: None,
+            require_utf8: true,
+            trailing_newline: true,
+        }
+    }
+}
+
+impl StyleOverrides {
+    /// Replaces the fields of `config` which are set in this override.
+    pub fn apply(&self, config: &mut StyleConfig) {
+        if let Some(max_line_length) = self.max_line_length {
+            config.max_line_length = max_line_length;
+        }
+        if let Some(max_file_lines) = self.max_file_lines {
+            config.max_file_lines = max_file_lines;
+        }
+        if let Some(line_ending) = self.line_ending {
+            config.line_ending = Some(line_ending);
+        }
+        if let Some(require_utf8) = self.require_utf8 {
+            config.require_utf8 = require_utf8;
+        }
+        if let Some(trailing_newline) = self.trailing_newline {
+            config.trailing_newline = trailing_newline;
+        }
+    }
+}
+
+pub(crate) fn check(text: &str, encoding: Encoding, config: &StyleConfig) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let bom = if text.starts_with('\u{feff}') {
+        0..'\u{feff}'.len_utf8()
+    } else {
+        0..0
+    };
+    if config.require_utf8 && encoding != Encoding::Utf8 {
+        // A byte order mark is unnecessary in UTF-8, so the fix also removes it.
+        diagnostics.push(
+            Diagnostic::warning(
+                bom.clone(),
+                format!("file is encoded as {encoding}, but Azure DevOps expects utf-8"),
+            )
+            .with_encoding_fix(
+                "re-encode the file as utf-8",
+                [TextEdit::delete(bom.clone())],
+                Encoding::Utf8,
+            ),
+        );
+    }
+
+    for (pos, ch) in text.match_indices('\u{feff}') {
+        if pos != 0 {
+            diagnostics.push(
+                Diagnostic::warning(
+                    pos..(pos + ch.len()),
+                    "byte order mark in the middle of the file",
+                )
+                .with_fix(
+                    "remove the byte order mark",
+                    [TextEdit::delete(pos..(pos + ch.len()))],
+                ),
+            );
+        }
+    }
+
+    // The fix repeats the file's last line break. The analyzer converts it to the detected line ending, but files
+    // which only use `\r` have none, so they keep `\r`.
+    if config.trailing_newline && text.len() > bom.end && !text.ends_with(['\r', '\n']) {
+        let line_break = lines(text).filter_map(line_break).last().unwrap_or("\n");
+        diagnostics.push(
+            Diagnostic::warning(
+                text.len()..text.len(),
+                "file does not end with a line break",
+            )
+            .with_fix(
+                "add a line break",
+                [TextEdit::insert(text.len(), line_break)],
+            ),
+        );
+    }
+
+    let mut start = 0;
+    for (index, line) in lines(text).enumerate() {
+        if config.max_file_lines != 0 && index == config.max_file_lines {
+            let count = lines(text).count();
+            diagnostics.push(Diagnostic::warning(
+                start..text.len(),
+                format!(
+                    "file has {count} lines, exceeding the maximum of {}",
+                    config.max_file_lines
+                ),
+            ));
+        }
+
+        let content = line.trim_end_matches(['\r', '\n']);
+        if config.max_line_length != 0 {
+            if let Some((excess, _)) = content.char_indices().nth(config.max_line_length) {
+                let len = content.chars().count();
+                diagnostics.push(Diagnostic::warning(
+                    (start + excess)..(start + content.len()),
+                    format!(
+                        "line is {len} characters long, exceeding the maximum of {}",
+                        config.max_line_length
+                    ),
+                ));
+            }
+        }
+
+        start += line.len();
+    }
+
+    if let Some(ending) = config.line_ending {
+        let edits = ending.normalize(text);
+        if let Some(first) = edits.edits().first() {
+            diagnostics.push(
+                Diagnostic::warning(first.span(), format!("line endings should be {ending}"))
+                    .with_fix(
+                        format!("convert line endings to {ending}"),
+                        edits.edits().to_vec(),
+                    ),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+/// Splits `text` into lines, including their line breaks. As in the parser, a line break is `\r\n`, `\r` or `\n`.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = match rest.find(['\r', '\n']) {
+            Some(pos) if rest[pos..].starts_with("\r\n") => pos + 2,
+            Some(pos) => pos + 1,
+            None => rest.len(),
+        };
+        let (line, tail) = rest.split_at(end);
+        rest = tail;
+        Some(line)
+    })
+}
+
+/// Gets the line break at the end of `line`, if any.
+fn line_break(line: &str) -> Option<&'static str> {
+    if line.ends_with("\r\n") {
+        Some("\r\n")
+    } else if line.ends_with('\n') {
+        Some("\n")
+    } else if line.ends_with('\r') {
+        Some("\r")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EditSet;
+
+    #[test]
+    fn line_length() {
+        let config = StyleConfig {
+            max_line_length: 5,
+            max_file_lines: 0,
+            line_ending: None,
+            require_utf8: true,
+            trailing_newline: false,
+        };
+
+        let diagnostics = check("12345\r\n1234567\nä23456", Encoding::Utf8, &config);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].span(), 12..14);
+        assert_eq!(
+            diagnostics[0].message(),
+            "line is 7 characters long, exceeding the maximum of 5"
+        );
+        assert_eq!(diagnostics[1].span(), 21..22);
+
+        let config = StyleConfig {
+            max_line_length: 0,
+            ..config
+        };
+        assert!(check("1234567", Encoding::Utf8, &config).is_empty());
+    }
+
+    #[test]
+    fn line_ending() {
+        let config = StyleConfig {
+            max_line_length: 0,
+            max_file_lines: 0,
+            line_ending: Some(LineEnding::Lf),
+            require_utf8: true,
+            trailing_newline: false,
+        };
+
+        let text = "a\nb\r\nc\r\n";
+        let diagnostics = check(text, Encoding::Utf8, &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span(), 3..4);
+        assert_eq!(diagnostics[0].message(), "line endings should be LF");
+        let fix = EditSet::new(diagnostics[0].fixes()[0].edits().to_vec()).unwrap();
+        assert_eq!(fix.apply(text), "a\nb\nc\n");
+
+        assert!(check("a\nb\n", Encoding::Utf8, &config).is_empty());
+        let config = StyleConfig {
+            line_ending: None,
+            ..config
+        };
+        assert!(check(text, Encoding::Utf8, &config).is_empty());
+    }
+
+    #[test]
+    fn encoding() {
+        let config = StyleConfig {
+            trailing_newline: false,
+            ..StyleConfig::default()
+        };
+
+        assert!(check("\u{feff}a: b", Encoding::Utf8, &config).is_empty());
+
+        let diagnostics = check("\u{feff}a: b", Encoding::Utf16Le, &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span(), 0..3);
+        assert_eq!(
+            diagnostics[0].message(),
+            "file is encoded as utf-16le, but Azure DevOps expects utf-8"
+        );
+        let fix = &diagnostics[0].fixes()[0];
+        assert_eq!(fix.encoding(), Some(Encoding::Utf8));
+        assert_eq!(
+            fix.apply("\u{feff}a: b", Encoding::Utf16Le).unwrap(),
+            b"a: b"
+        );
+
+        let diagnostics = check("a: b", Encoding::Utf32Be, &config);
+        assert_eq!(diagnostics[0].span(), 0..0);
+        assert_eq!(
+            diagnostics[0].fixes()[0]
+                .apply("a: b", Encoding::Utf32Be)
+                .unwrap(),
+            b"a: b"
+        );
+
+        let config = StyleConfig {
+            require_utf8: false,
+            ..config
+        };
+        assert!(check("a: b", Encoding::Utf32Be, &config).is_empty());
+    }
+
+    #[test]
+    fn interior_bom() {
+        let text = "\u{feff}a: b\n\u{feff}c: d\n";
+        let diagnostics = check(text, Encoding::Utf8, &StyleConfig::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span(), 8..11);
+        let fix = EditSet::new(diagnostics[0].fixes()[0].edits().to_vec()).unwrap();
+        assert_eq!(fix.apply(text), "\u{feff}a: b\nc: d\n");
+    }
+
+    #[test]
+    fn trailing_newline() {
+        let config = StyleConfig::default();
+        assert!(check("", Encoding::Utf8, &config).is_empty());
+        assert!(check("\u{feff}", Encoding::Utf8, &config).is_empty());
+        assert!(check("a: b\n", Encoding::Utf8, &config).is_empty());
+        assert!(check("a: b\rc: d\r", Encoding::Utf8, &config).is_empty());
+
+        let text = "a: b\r\nc: d";
+        let diagnostics = check(text, Encoding::Utf8, &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span(), 10..10);
+        assert_eq!(
+            diagnostics[0].message(),
+            "file does not end with a line break"
+        );
+        let fix = EditSet::new(diagnostics[0].fixes()[0].edits().to_vec()).unwrap();
+        assert_eq!(fix.apply(text), "a: b\r\nc: d\r\n");
+
+        let text = "a: b\rc: d";
+        let diagnostics = check(text, Encoding::Utf8, &config);
+        let fix = EditSet::new(diagnostics[0].fixes()[0].edits().to_vec()).unwrap();
+        assert_eq!(fix.apply(text), "a: b\rc: d\r");
+
+        let config = StyleConfig {
+            trailing_newline: false,
+            ..config
+        };
+        assert!(check(text, Encoding::Utf8, &config).is_empty());
+    }
+
+    #[test]
+    fn file_lines() {
+        let config = StyleConfig {
+            max_line_length: 0,
+            max_file_lines: 2,
+            line_ending: None,
+            require_utf8: true,
+            trailing_newline: false,
+        };
+
+        assert!(check("a\nb\n", Encoding::Utf8, &config).is_empty());
+        let diagnostics = check("a\nb\nc\nd", Encoding::Utf8, &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span(), 4..7);
+        assert_eq!(
+            diagnostics[0].message(),
+            "file has 4 lines, exceeding the maximum of 2"
+        );
+
+        let config = StyleConfig {
+            max_file_lines: 0,
+            ..config
+        };
+        assert!(check("a\nb\nc\nd", Encoding::Utf8, &config).is_empty());
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(
+            lines("a\rb\r\nc\n\rd").collect::<Vec<_>>(),
+            ["a\r", "b\r\n", "c\n", "\r", "d"]
+        );
+        assert_eq!(lines("").count(), 0);
+
+        let config = StyleConfig {
+            max_line_length: 2,
+            max_file_lines: 2,
+            line_ending: None,
+            require_utf8: true,
+            trailing_newline: false,
+        };
+        let diagnostics = check("a\rb\rc", Encoding::Utf8, &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message(),
+            "file has 3 lines, exceeding the maximum of 2"
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let mut config = StyleConfig::default();
+        StyleOverrides {
+            max_line_length: Some(80),
+            line_ending: Some(LineEnding::CrLf),
+            ..StyleOverrides::default()
+        }
+        .apply(&mut config);
+        assert_eq!(
+            config,
+            StyleConfig {
+                max_line_length: 80,
+                line_ending: Some(LineEnding::CrLf),
+                ..StyleConfig::default()
+            }
+        );
+    }
+}
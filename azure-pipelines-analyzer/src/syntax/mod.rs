@@ -4,7 +4,12 @@ use std::ops::Range;
 
 mod parser;
 
-pub use self::parser::{parse, Parse};
+pub(crate) use self::parser::{decode_with_options, parse_isolated, Decoded, ParseOptions};
+
+pub use self::parser::{
+    parse, parse_cancellable, parse_with_cache, Encoding, Expected, InvalidData, Parse, ParseCache,
+    ParseError,
+};
 
 pub type Span = Range<usize>;
 
@@ -1,117 +1,262 @@
-use std::{
-    borrow::Cow,
-    char::DecodeUtf16Error,
-    error::Error,
-    fmt,
-    str::{self, Utf8Error},
-};
-
-pub(crate) fn decode(text: &[u8]) -> Result<Cow<'_, str>, DecodeError> {
-    match text {
-        // Explicit BOM
-        [0x00, 0x00, 0xfe, 0xff, ..] => decode_utf32_be(text).map(Cow::Owned),
-        // ASCII first character
-        [0x00, 0x00, 0x00, _, ..] => decode_utf32_be(text).map(Cow::Owned),
-        // Explicit BOM
-        [0xff, 0xfe, 0x00, 0x00, ..] => decode_utf32_le(text).map(Cow::Owned),
-        // ASCII first character
-        [_, 0x00, 0x00, 0x00, ..] => decode_utf32_le(text).map(Cow::Owned),
-        // Explicit BOM
-        [0xfe, 0xff, ..] => decode_utf16_be(text).map(Cow::Owned),
-        // ASCII first character
-        [0x00, _, ..] => decode_utf16_be(text).map(Cow::Owned),
-        // Explicit BOM
-        [0xff, 0xfe, ..] => decode_utf16_le(text).map(Cow::Owned),
-        // ASCII first character
-        [_, 0x00, ..] => decode_utf16_le(text).map(Cow::Owned),
-        // Explicit BOM
-        [0xef, 0xbb, 0xbf, ..] => decode_utf8(text).map(Cow::Borrowed),
-        // Default
-        _ => decode_utf8(text).map(Cow::Borrowed),
+use std::{borrow::Cow, error::Error, fmt, str};
+
+use serde::{Deserialize, Serialize};
+
+use super::{InvalidData, Span};
+
+/// The encoding of a source file, detected from its byte order mark or the position of null bytes in its first
+/// character, as described in the YAML specification.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Encoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-32be")]
+    Utf32Be,
+    #[serde(rename = "utf-32le")]
+    Utf32Le,
+}
+
+impl Encoding {
+    pub fn detect(text: &[u8]) -> Self {
+        match text {
+            // Explicit BOM
+            [0x00, 0x00, 0xfe, 0xff, ..] => Encoding::Utf32Be,
+            // ASCII first character
+            [0x00, 0x00, 0x00, _, ..] => Encoding::Utf32Be,
+            // Explicit BOM
+            [0xff, 0xfe, 0x00, 0x00, ..] => Encoding::Utf32Le,
+            // ASCII first character
+            [_, 0x00, 0x00, 0x00, ..] => Encoding::Utf32Le,
+            // Explicit BOM
+            [0xfe, 0xff, ..] => Encoding::Utf16Be,
+            // ASCII first character
+            [0x00, _, ..] => Encoding::Utf16Be,
+            // Explicit BOM
+            [0xff, 0xfe, ..] => Encoding::Utf16Le,
+            // ASCII first character
+            [_, 0x00, ..] => Encoding::Utf16Le,
+            // Explicit BOM, or default
+            _ => Encoding::Utf8,
+        }
+    }
+
+    /// Encodes text, such as the result of applying edits to a decoded file, back into this encoding.
+    ///
+    /// Decoding preserves any byte order mark as a leading `U+FEFF` character, so a file which is decoded, edited
+    /// and encoded again keeps its original byte order mark.
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Encoding::Utf32Be => text
+                .chars()
+                .flat_map(|ch| u32::from(ch).to_be_bytes())
+                .collect(),
+            Encoding::Utf32Le => text
+                .chars()
+                .flat_map(|ch| u32::from(ch).to_le_bytes())
+                .collect(),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf32Be => "utf-32be",
+            Encoding::Utf32Le => "utf-32le",
+        }
+    }
+}
+
+/// Decodes `text`, which should have been detected as `encoding`.
+pub(crate) fn decode(text: &[u8], encoding: Encoding) -> Result<Cow<'_, str>, DecodeError> {
+    match encoding {
+        Encoding::Utf8 => decode_utf8(text).map(Cow::Borrowed),
+        Encoding::Utf16Be => decode_utf16_be(text).map(Cow::Owned),
+        Encoding::Utf16Le => decode_utf16_le(text).map(Cow::Owned),
+        Encoding::Utf32Be => decode_utf32_be(text).map(Cow::Owned),
+        Encoding::Utf32Le => decode_utf32_le(text).map(Cow::Owned),
     }
 }
 
+/// The first invalid data found while decoding a file.
 #[derive(Debug)]
-pub(crate) enum DecodeError {
-    Utf8(Utf8Error),
-    Utf16(Option<DecodeUtf16Error>),
-    Utf32,
+pub(crate) struct DecodeError {
+    /// The byte range of the invalid data in the undecoded file.
+    pub span: Span,
+    pub reason: InvalidData,
 }
 
-fn decode_utf32_be(text: &[u8]) -> Result<String, DecodeError> {
-    if text.len() % 4 != 0 {
-        return Err(DecodeError::Utf32);
+impl DecodeError {
+    fn new(span: Span, reason: InvalidData) -> Self {
+        DecodeError { span, reason }
     }
+}
 
-    text.chunks(4)
-        .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]])
-        .map(u32::from_be_bytes)
-        .map(char::from_u32)
-        .collect::<Option<String>>()
-        .ok_or(DecodeError::Utf32)
+fn decode_utf32_be(text: &[u8]) -> Result<String, DecodeError> {
+    decode_utf32(text, u32::from_be_bytes)
 }
 
 fn decode_utf32_le(text: &[u8]) -> Result<String, DecodeError> {
-    if text.len() % 4 != 0 {
-        return Err(DecodeError::Utf32);
+    decode_utf32(text, u32::from_le_bytes)
+}
+
+fn decode_utf32(text: &[u8], from_bytes: fn([u8; 4]) -> u32) -> Result<String, DecodeError> {
+    let chunks = text.chunks_exact(4);
+    let remainder = chunks.remainder().len();
+
+    let mut result = String::with_capacity(text.len() / 4);
+    for (index, chunk) in chunks.enumerate() {
+        let value = from_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        match char::from_u32(value) {
+            Some(ch) => result.push(ch),
+            None => {
+                return Err(DecodeError::new(
+                    index * 4..index * 4 + 4,
+                    InvalidData::InvalidCodePoint(value),
+                ))
+            }
+        }
     }
 
-    text.chunks(4)
-        .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]])
-        .map(u32::from_le_bytes)
-        .map(char::from_u32)
-        .collect::<Option<String>>()
-        .ok_or(DecodeError::Utf32)
+    if remainder != 0 {
+        return Err(DecodeError::new(
+            text.len() - remainder..text.len(),
+            InvalidData::Truncated,
+        ));
+    }
+    Ok(result)
 }
 
 fn decode_utf16_be(text: &[u8]) -> Result<String, DecodeError> {
-    if text.len() % 2 != 0 {
-        return Err(DecodeError::Utf16(None));
-    }
-
-    char::decode_utf16(
-        text.chunks(2)
-            .map(|chunk| [chunk[0], chunk[1]])
-            .map(u16::from_be_bytes),
-    )
-    .collect::<Result<String, DecodeUtf16Error>>()
-    .map_err(|err| DecodeError::Utf16(Some(err)))
+    decode_utf16(text, u16::from_be_bytes)
 }
 
 fn decode_utf16_le(text: &[u8]) -> Result<String, DecodeError> {
-    if text.len() % 2 != 0 {
-        return Err(DecodeError::Utf16(None));
+    decode_utf16(text, u16::from_le_bytes)
+}
+
+fn decode_utf16(text: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<String, DecodeError> {
+    let chunks = text.chunks_exact(2);
+    let remainder = chunks.remainder().len();
+
+    let mut result = String::with_capacity(text.len() / 2);
+    let mut offset = 0;
+    for ch in char::decode_utf16(chunks.map(|chunk| from_bytes([chunk[0], chunk[1]]))) {
+        match ch {
+            Ok(ch) => {
+                result.push(ch);
+                offset += ch.len_utf16() * 2;
+            }
+            Err(err) => {
+                return Err(DecodeError::new(
+                    offset..offset + 2,
+                    InvalidData::UnpairedSurrogate(err.unpaired_surrogate()),
+                ))
+            }
+        }
     }
 
-    char::decode_utf16(
-        text.chunks(2)
-            .map(|chunk| [chunk[0], chunk[1]])
-            .map(u16::from_le_bytes),
-    )
-    .collect::<Result<String, DecodeUtf16Error>>()
-    .map_err(|err| DecodeError::Utf16(Some(err)))
+    if remainder != 0 {
+        return Err(DecodeError::new(
+            text.len() - remainder..text.len(),
+            InvalidData::Truncated,
+        ));
+    }
+    Ok(result)
 }
 
 fn decode_utf8(text: &[u8]) -> Result<&str, DecodeError> {
-    str::from_utf8(text).map_err(DecodeError::Utf8)
+    str::from_utf8(text).map_err(|err| {
+        let start = err.valid_up_to();
+        match err.error_len() {
+            Some(len) => DecodeError::new(start..start + len, InvalidData::InvalidSequence),
+            None => DecodeError::new(start..text.len(), InvalidData::Truncated),
+        }
+    })
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl fmt::Display for DecodeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            DecodeError::Utf8(_) => write!(f, "source file was not valid utf-8"),
-            DecodeError::Utf16(_) => write!(f, "source file was not valid utf-16"),
-            DecodeError::Utf32 => write!(f, "source file was not valid utf-32"),
-        }
+        write!(f, "{} at byte {}", self.reason, self.span.start)
     }
 }
 
-impl Error for DecodeError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        match self {
-            DecodeError::Utf8(err) => Some(err),
-            DecodeError::Utf16(Some(err)) => Some(err),
-            _ => None,
+impl Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "\u{feff}key: välue 🚀\n";
+        for encoding in [
+            Encoding::Utf8,
+            Encoding::Utf16Be,
+            Encoding::Utf16Le,
+            Encoding::Utf32Be,
+            Encoding::Utf32Le,
+        ] {
+            let bytes = encoding.encode(text);
+            assert_eq!(Encoding::detect(&bytes), encoding);
+            assert_eq!(decode(&bytes, encoding).unwrap(), text);
         }
     }
+
+    #[test]
+    fn detect_without_bom() {
+        let text = "key: value\n";
+        for encoding in [
+            Encoding::Utf16Be,
+            Encoding::Utf16Le,
+            Encoding::Utf32Be,
+            Encoding::Utf32Le,
+        ] {
+            let bytes = encoding.encode(text);
+            assert_eq!(Encoding::detect(&bytes), encoding);
+            assert_eq!(decode(&bytes, encoding).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn invalid_data() {
+        let err = decode(b"ab\xffcd", Encoding::Utf8).unwrap_err();
+        assert_eq!(err.span, 2..3);
+        assert_eq!(err.reason, InvalidData::InvalidSequence);
+        assert_eq!(err.to_string(), "invalid byte sequence at byte 2");
+
+        let err = decode(b"ab\xe2\x82", Encoding::Utf8).unwrap_err();
+        assert_eq!(err.span, 2..4);
+        assert_eq!(err.reason, InvalidData::Truncated);
+
+        let err = decode(b"a\0\x00\xd8b\0", Encoding::Utf16Le).unwrap_err();
+        assert_eq!(err.span, 2..4);
+        assert_eq!(err.reason, InvalidData::UnpairedSurrogate(0xd800));
+
+        let err = decode(b"\0a\0", Encoding::Utf16Be).unwrap_err();
+        assert_eq!(err.span, 2..3);
+        assert_eq!(err.reason, InvalidData::Truncated);
+
+        let err = decode(b"a\0\0\0\0\0\x11\0", Encoding::Utf32Le).unwrap_err();
+        assert_eq!(err.span, 4..8);
+        assert_eq!(err.reason, InvalidData::InvalidCodePoint(0x110000));
+
+        let err = decode(b"\0\0\0a\0", Encoding::Utf32Be).unwrap_err();
+        assert_eq!(err.span, 4..5);
+        assert_eq!(err.reason, InvalidData::Truncated);
+    }
 }
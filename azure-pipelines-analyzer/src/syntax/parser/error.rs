@@ -0,0 +1,138 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{is_break, Encoding};
+
+/// The cause of a syntax error, attached to parser diagnostics so that tools can react to specific errors without
+/// matching on messages.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The file was not valid in its detected encoding. The diagnostic's span covers the first invalid bytes.
+    InvalidEncoding {
+        encoding: Encoding,
+        reason: InvalidData,
+    },
+    /// The next character did not start any of the expected tokens. `found` is `None` at the end of the input.
+    UnexpectedToken {
+        expected: Vec<Expected>,
+        found: Option<char>,
+    },
+    /// A character which is not allowed inside a token.
+    InvalidCharacter { found: char, expected: Expected },
+    /// A line was indented by fewer spaces than the enclosing node requires.
+    BadIndent { expected: u32, found: u32 },
+    /// A comment was not separated from the preceding value by whitespace.
+    UnseparatedComment,
+}
+
+/// Why a file could not be decoded in its detected encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum InvalidData {
+    /// A utf-8 byte sequence which does not encode any character.
+    InvalidSequence,
+    /// The file ended partway through a character.
+    Truncated,
+    /// A utf-16 surrogate code unit which was not part of a surrogate pair.
+    UnpairedSurrogate(u16),
+    /// A utf-32 code unit which is not a unicode scalar value.
+    InvalidCodePoint(u32),
+}
+
+/// A token or syntactic construct which the parser expected to find.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Expected {
+    Char(char),
+    Digit,
+    Comment,
+    EndOfLine,
+    DirectiveName,
+    YamlVersion,
+    TagHandle,
+    TagPrefix,
+    TagSuffix,
+    VerbatimTag,
+    AnchorName,
+    FlowContent,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidEncoding { encoding, reason } => {
+                write!(f, "source file was not valid {encoding}: {reason}")
+            }
+            ParseError::UnexpectedToken { expected, found } => {
+                match expected.as_slice() {
+                    [] => write!(f, "unexpected token")?,
+                    [expected] => write!(f, "expected {expected}")?,
+                    [init @ .., last] => {
+                        if init.len() == 1 {
+                            write!(f, "expected ")?;
+                        } else {
+                            write!(f, "expected one of ")?;
+                        }
+                        for (i, expected) in init.iter().enumerate() {
+                            if i != 0 {
+                                write!(f, ", ")?;
+                            }
+                            write!(f, "{expected}")?;
+                        }
+                        write!(f, " or {last}")?;
+                    }
+                }
+                match found {
+                    Some(ch) if is_break(*ch) => write!(f, ", found line break"),
+                    Some(ch) => write!(f, ", found {ch:?}"),
+                    None => write!(f, ", found end of input"),
+                }
+            }
+            ParseError::InvalidCharacter { found, expected } => {
+                write!(f, "invalid character {found:?} in {expected}")
+            }
+            ParseError::BadIndent { expected, found } => write!(
+                f,
+                "expected line to be indented {expected} spaces, found {found}"
+            ),
+            ParseError::UnseparatedComment => {
+                write!(f, "comments must be separated from values")
+            }
+        }
+    }
+}
+
+impl fmt::Display for InvalidData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidData::InvalidSequence => write!(f, "invalid byte sequence"),
+            InvalidData::Truncated => write!(f, "incomplete character at end of file"),
+            InvalidData::UnpairedSurrogate(unit) => write!(f, "unpaired surrogate {unit:#06x}"),
+            InvalidData::InvalidCodePoint(unit) => write!(f, "invalid code point {unit:#x}"),
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Char(ch) => write!(f, "'{ch}'"),
+            Expected::Digit => write!(f, "digit"),
+            Expected::Comment => write!(f, "comment"),
+            Expected::EndOfLine => write!(f, "end of line"),
+            Expected::DirectiveName => write!(f, "directive name"),
+            Expected::YamlVersion => write!(f, "YAML version"),
+            Expected::TagHandle => write!(f, "tag handle"),
+            Expected::TagPrefix => write!(f, "tag prefix"),
+            Expected::TagSuffix => write!(f, "tag suffix"),
+            Expected::VerbatimTag => write!(f, "verbatim tag"),
+            Expected::AnchorName => write!(f, "anchor name"),
+            Expected::FlowContent => write!(f, "flow content"),
+        }
+    }
+}
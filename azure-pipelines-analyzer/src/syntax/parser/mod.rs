@@ -1,51 +1,317 @@
 mod encoding;
+mod error;
 #[cfg(test)]
 mod tests;
 
-use std::{iter::empty, str::Chars, vec};
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+    iter::empty,
+    str::Chars,
+    time::Instant,
+    vec,
+};
+
+use rowan::{
+    Checkpoint, GreenNode, GreenNodeBuilder, NodeCache, NodeOrToken, SyntaxNode, WalkEvent,
+};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 
-use rowan::{Checkpoint, GreenNode, GreenNodeBuilder, SyntaxNode};
-use serde::Serialize;
+use self::encoding::DecodeError;
+pub use self::{
+    encoding::Encoding,
+    error::{Expected, InvalidData, ParseError},
+};
 
 use crate::{
-    diagnostic::Severity,
+    isolation::isolate,
     syntax::SyntaxKind::{self, *},
-    Diagnostic,
+    AnalysisObserver, CancellationToken, Cancelled, Diagnostic, FileId, Phase, PhaseEvent,
 };
 
 use super::{Span, Yaml};
 
-#[derive(Debug, Serialize)]
+/// The result of parsing a file.
+///
+/// This stores the immutable green tree rather than a `SyntaxNode`, so that it is `Send` and `Sync` and can be cached
+/// and shared between threads. Syntax nodes are cheap to create on demand from the green tree.
+#[derive(Clone)]
 pub struct Parse {
-    node: SyntaxNode<Yaml>,
+    green: GreenNode,
     errors: Vec<Diagnostic>,
 }
 
+/// Interns the nodes and tokens of syntax trees, so that identical subtrees are only stored once.
+#[derive(Debug, Default)]
+pub struct ParseCache(NodeCache);
+
+impl ParseCache {
+    pub fn new() -> Self {
+        ParseCache::default()
+    }
+}
+
 pub fn parse(text: &[u8]) -> Parse {
-    let text = match encoding::decode(text) {
-        Ok(text) => text,
+    parse_with_cache(text, &mut ParseCache::default())
+}
+
+/// Parses a file, sharing identical nodes and tokens with other files parsed using the same cache.
+///
+/// Pipelines tend to repeat the same short tokens many times (keys such as `task` or `displayName`), which are only
+/// stored once when files are parsed with the same cache.
+pub fn parse_with_cache(text: &[u8], cache: &mut ParseCache) -> Parse {
+    parse_with_options(text, cache, &ParseOptions::default())
+        .expect("parsing without a cancellation token cannot be cancelled")
+}
+
+/// Parses a file, stopping early if `cancellation` is cancelled before parsing completes.
+///
+/// The token is checked as each token is added to the tree. Once it is cancelled, the parser stops consuming input, so
+/// the remaining rules finish quickly as if the end of the input had been reached.
+pub fn parse_cancellable(
+    text: &[u8],
+    cache: &mut ParseCache,
+    cancellation: &CancellationToken,
+) -> Result<Parse, Cancelled> {
+    let options = ParseOptions {
+        cancellation: Some(cancellation),
+        ..Default::default()
+    };
+    parse_with_options(text, cache, &options)
+}
+
+#[derive(Default)]
+pub(crate) struct ParseOptions<'a> {
+    /// The file diagnostics are attributed to.
+    pub file: Option<FileId>,
+    pub cancellation: Option<&'a CancellationToken>,
+    pub observer: Option<&'a dyn AnalysisObserver>,
+}
+
+/// A file decoded to UTF-8, along with the encoding it was detected to use.
+pub(crate) struct Decoded<'a> {
+    pub encoding: Encoding,
+    pub text: Result<Cow<'a, str>, DecodeError>,
+}
+
+pub(crate) fn parse_with_options(
+    text: &[u8],
+    cache: &mut ParseCache,
+    options: &ParseOptions,
+) -> Result<Parse, Cancelled> {
+    parse_decoded(&decode_with_options(text, options), cache, options)
+}
+
+/// Detects the encoding of a file and decodes it, so that the result can be shared by the parser and other checks.
+pub(crate) fn decode_with_options<'a>(text: &'a [u8], options: &ParseOptions) -> Decoded<'a> {
+    let start = Instant::now();
+    let encoding = Encoding::detect(text);
+    let decoded = Decoded {
+        encoding,
+        text: encoding::decode(text, encoding),
+    };
+    options.phase_finished(
+        Phase::Decode,
+        start,
+        text.len(),
+        decoded.text.is_err() as usize,
+    );
+    decoded
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(file = ?options.file, encoding = %decoded.encoding))
+)]
+pub(crate) fn parse_decoded(
+    decoded: &Decoded,
+    cache: &mut ParseCache,
+    options: &ParseOptions,
+) -> Result<Parse, Cancelled> {
+    if let Some(cancellation) = options.cancellation {
+        cancellation.check()?;
+    }
+
+    let parse = match &decoded.text {
+        Ok(text) => {
+            let start = Instant::now();
+            let mut parser = Parser::with_cache(text, &mut cache.0);
+            parser.cancellation = options.cancellation;
+
+            // todo
+            parser.directive();
+            // parser.flow_node(0, Context::FlowIn);
+            parser.unparsed();
+
+            if parser.cancelled {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("parse cancelled");
+                return Err(Cancelled);
+            }
+            let parse = parser.finish();
+            options.phase_finished(Phase::Parse, start, text.len(), parse.errors.len());
+            #[cfg(feature = "tracing")]
+            tracing::debug!(errors = parse.errors.len(), "parsed file");
+            parse
+        }
         Err(err) => {
-            return Parse {
-                errors: vec![Diagnostic::new(0..0, Severity::Error, err)],
-                node: SyntaxNode::new_root(GreenNode::new(Error.into(), empty())),
+            #[cfg(feature = "tracing")]
+            tracing::debug!(error = %err, "failed to decode file");
+            Parse {
+                errors: vec![Diagnostic::from_parse_error(
+                    err.span.clone(),
+                    ParseError::InvalidEncoding {
+                        encoding: decoded.encoding,
+                        reason: err.reason,
+                    },
+                )],
+                green: GreenNode::new(Error.into(), empty()),
             }
         }
     };
 
-    let mut parser = Parser::new(text.as_ref());
+    Ok(match options.file {
+        Some(file) => parse.with_file(file),
+        None => parse,
+    })
+}
+
+/// Parses a file, converting any panic into an internal error diagnostic, so that a bug triggered by one file does
+/// not abort the analysis of others.
+pub(crate) fn parse_isolated(
+    decoded: &Decoded,
+    cache: &mut ParseCache,
+    options: &ParseOptions,
+) -> Result<Parse, Cancelled> {
+    isolate_parse(options, || parse_decoded(decoded, cache, options))
+}
+
+fn isolate_parse(
+    options: &ParseOptions,
+    parse: impl FnOnce() -> Result<Parse, Cancelled>,
+) -> Result<Parse, Cancelled> {
+    match isolate(parse) {
+        Ok(parse) => parse,
+        Err(panic) => {
+            let parse = Parse {
+                errors: vec![panic.into_diagnostic("parsing")],
+                green: GreenNode::new(Error.into(), empty()),
+            };
+            Ok(match options.file {
+                Some(file) => parse.with_file(file),
+                None => parse,
+            })
+        }
+    }
+}
+
+impl ParseOptions<'_> {
+    fn phase_finished(&self, phase: Phase, start: Instant, input_len: usize, diagnostics: usize) {
+        if let Some(observer) = self.observer {
+            observer.phase_finished(&PhaseEvent {
+                file: self.file,
+                phase,
+                elapsed: start.elapsed(),
+                input_len,
+                diagnostics,
+            });
+        }
+    }
+}
+
+impl Parse {
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    fn with_file(mut self, id: FileId) -> Self {
+        self.errors = self
+            .errors
+            .into_iter()
+            .map(|error| error.with_file(id))
+            .collect();
+        self
+    }
+
+    /// Gets the text of the syntax tree.
+    ///
+    /// The tree is lossless, so this is the decoded input, unless the file could not be decoded, in which case the
+    /// tree is empty.
+    pub fn text(&self) -> String {
+        self.syntax().to_string()
+    }
+
+    /// Formats the syntax tree as indented text, with the kind and span of each node and the text of each token.
+    ///
+    /// The format is intended for debugging and bug reports, and may change between versions. Unlike the `Debug`
+    /// implementation, token text is never truncated.
+    pub fn debug_dump(&self) -> String {
+        let mut dump = String::new();
+        let mut depth = 0;
+        for event in self.syntax().preorder_with_tokens() {
+            match event {
+                WalkEvent::Enter(element) => {
+                    let indent = "  ".repeat(depth);
+                    match element {
+                        NodeOrToken::Node(node) => {
+                            writeln!(dump, "{indent}{:?}@{:?}", node.kind(), node.text_range())
+                        }
+                        NodeOrToken::Token(token) => writeln!(
+                            dump,
+                            "{indent}{:?}@{:?} {:?}",
+                            token.kind(),
+                            token.text_range(),
+                            token.text()
+                        ),
+                    }
+                    .expect("writing to a string cannot fail");
+                    depth += 1;
+                }
+                WalkEvent::Leave(_) => depth -= 1,
+            }
+        }
+        dump
+    }
+
+    pub(super) fn syntax(&self) -> SyntaxNode<Yaml> {
+        SyntaxNode::new_root(self.green.clone())
+    }
+}
 
-    // todo
-    parser.directive();
-    // parser.flow_node(0, Context::FlowIn);
+impl fmt::Debug for Parse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parse")
+            .field("node", &self.syntax())
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
 
-    parser.finish()
+impl Serialize for Parse {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Parse", 2)?;
+        state.serialize_field("node", &self.syntax())?;
+        state.serialize_field("errors", &self.errors)?;
+        state.end()
+    }
 }
 
-struct Parser<'t> {
+struct Parser<'t, 'c> {
     text: &'t str,
+    pos: usize,
+    peeked: Option<char>,
     iter: Chars<'t>,
-    builder: GreenNodeBuilder<'static>,
+    builder: GreenNodeBuilder<'c>,
     diagnostics: Vec<Diagnostic>,
+    cancellation: Option<&'c CancellationToken>,
+    /// Set once `cancellation` is cancelled, after which the rest of the input is hidden from the parser.
+    cancelled: bool,
+    /// Tokens which were tried and not found at `expected_pos`, reported if parsing fails at that position.
+    expected: Vec<Expected>,
+    expected_pos: usize,
 
     #[cfg(debug_assertions)]
     peek_count: std::sync::atomic::AtomicU32,
@@ -67,16 +333,33 @@ struct Marker {
     checkpoint: Checkpoint,
 }
 
-impl<'t> Parser<'t> {
+impl<'t> Parser<'t, 'static> {
+    #[cfg(test)]
     fn new(text: &'t str) -> Self {
-        let mut builder = GreenNodeBuilder::new();
+        Parser::with_builder(text, GreenNodeBuilder::new())
+    }
+}
+
+impl<'t, 'c> Parser<'t, 'c> {
+    fn with_cache(text: &'t str, cache: &'c mut NodeCache) -> Self {
+        Parser::with_builder(text, GreenNodeBuilder::with_cache(cache))
+    }
+
+    fn with_builder(text: &'t str, mut builder: GreenNodeBuilder<'c>) -> Self {
         builder.start_node(Root.into());
 
+        let mut iter = text.chars();
         Parser {
             text,
-            iter: text.chars(),
+            pos: 0,
+            peeked: iter.next(),
+            iter,
             builder,
             diagnostics: Vec::new(),
+            cancellation: None,
+            cancelled: false,
+            expected: Vec::new(),
+            expected_pos: 0,
             #[cfg(debug_assertions)]
             peek_count: std::sync::atomic::AtomicU32::new(0),
         }
@@ -85,16 +368,17 @@ impl<'t> Parser<'t> {
     fn finish(mut self) -> Parse {
         self.builder.finish_node();
         Parse {
-            node: SyntaxNode::new_root(self.builder.finish()),
+            green: self.builder.finish(),
             errors: self.diagnostics,
         }
     }
 
     // c-nb-comment-text
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn comment_text(&mut self) {
         let start = self.marker();
         if !self.eat_char('#') {
-            return self.error(start.pos, "expected '#'", is_break);
+            return self.expected(start.pos, [Expected::Char('#')], is_break);
         }
         self.token(CommentToken, start.pos);
 
@@ -105,14 +389,19 @@ impl<'t> Parser<'t> {
     }
 
     // s-l-comments
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn separated_line_comments(&mut self) {
         if self.peek() == Some('#') {
             let start = self.pos();
             self.bump();
-            return self.error(start, "comments must be separated from values", is_break);
+            return self.error(start, ParseError::UnseparatedComment, is_break);
         }
-        if self.try_inline_separator() && self.peek() == Some('#') {
-            self.comment_text();
+        if self.try_inline_separator() {
+            if self.peek() == Some('#') {
+                self.comment_text();
+            } else {
+                self.note_expected(Expected::Comment);
+            }
         }
 
         if self.is(is_break) {
@@ -120,13 +409,14 @@ impl<'t> Parser<'t> {
         } else if self.is_end_of_input() {
             return;
         } else if !self.is_start_of_line() {
-            return self.error(self.pos(), "expected end of line", is_break);
+            return self.expected(self.pos(), [Expected::EndOfLine], is_break);
         }
 
         self.line_comments();
     }
 
     // l-comment*
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn line_comments(&mut self) {
         while self.is_inline_separator()
             && matches!(
@@ -146,6 +436,7 @@ impl<'t> Parser<'t> {
     }
 
     // ns-flow-node(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_node(&mut self, indent: u32, context: Context) {
         let start = self.marker();
 
@@ -164,6 +455,7 @@ impl<'t> Parser<'t> {
     }
 
     // ns-flow-content(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_content(&mut self, indent: u32, context: Context) {
         let start = self.marker();
         match self.peek() {
@@ -174,36 +466,44 @@ impl<'t> Parser<'t> {
                 self.flow_yaml_content(indent, context)
             }
             Some('[' | '{' | '\'' | '"') => self.flow_json_content(indent, context),
-            _ => return self.error(self.pos(), "invalid flow content", context.recovery_fn()),
+            _ => return self.expected(self.pos(), [Expected::FlowContent], context.recovery_fn()),
         }
         self.node_at(start, FlowContent);
     }
 
     // ns-flow-yaml-content(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_yaml_content(&mut self, indent: u32, context: Context) {
         todo!()
     }
 
     // ns-flow-json-content(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_json_content(&mut self, indent: u32, context: Context) {
         match self.peek() {
             Some('[') => self.flow_sequence(indent, context),
             Some('{') => self.flow_mapping(indent, context),
             Some('\'') => self.single_quoted(indent, context),
             Some('"') => self.double_quoted(indent, context),
-            _ => self.error(
+            _ => self.expected(
                 self.pos(),
-                "expected one of '[', '{', '\"' or '''",
+                [
+                    Expected::Char('['),
+                    Expected::Char('{'),
+                    Expected::Char('"'),
+                    Expected::Char('\''),
+                ],
                 context.recovery_fn(),
             ),
         }
     }
 
     // c-flow-sequence(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_sequence(&mut self, indent: u32, context: Context) {
         let start = self.marker();
         if !self.eat_char('[') {
-            return self.error(self.pos(), "expected '['", context.recovery_fn());
+            return self.expected(self.pos(), [Expected::Char('[')], context.recovery_fn());
         }
         self.token(SequenceStart, start.pos);
 
@@ -212,7 +512,7 @@ impl<'t> Parser<'t> {
         self.flow_sequence_entries(indent, context.in_flow());
 
         if !self.eat_char(']') {
-            return self.error(self.pos(), "expected ']'", context.recovery_fn());
+            return self.expected(self.pos(), [Expected::Char(']')], context.recovery_fn());
         }
         self.token(SequenceEnd, start.pos);
 
@@ -220,20 +520,23 @@ impl<'t> Parser<'t> {
     }
 
     // ns-s-flow-seq-entries
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_sequence_entries(&mut self, indent: u32, context: Context) {
         todo!()
     }
 
     // ns-flow-seq-entry
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_sequence_entry(&mut self, indent: u32, context: Context) {
         todo!()
     }
 
     // c-flow-mapping(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_mapping(&mut self, indent: u32, context: Context) {
         let start = self.marker();
         if !self.eat_char('{') {
-            return self.error(self.pos(), "expected '{'", context.recovery_fn());
+            return self.expected(self.pos(), [Expected::Char('{')], context.recovery_fn());
         }
         self.token(MappingStart, start.pos);
 
@@ -241,10 +544,11 @@ impl<'t> Parser<'t> {
     }
 
     // c-single-quoted(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn single_quoted(&mut self, indent: u32, context: Context) {
         let start = self.marker();
         if !self.eat_char('\'') {
-            return self.error(self.pos(), "expected '''", context.recovery_fn());
+            return self.expected(self.pos(), [Expected::Char('\'')], context.recovery_fn());
         }
         self.token(SingleQuote, start.pos);
 
@@ -252,10 +556,11 @@ impl<'t> Parser<'t> {
     }
 
     // c-double-quoted(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn double_quoted(&mut self, indent: u32, context: Context) {
         let start = self.marker();
         if !self.eat_char('"') {
-            return self.error(self.pos(), "expected '\"'", context.recovery_fn());
+            return self.expected(self.pos(), [Expected::Char('"')], context.recovery_fn());
         }
         self.token(DoubleQuote, start.pos);
 
@@ -263,13 +568,17 @@ impl<'t> Parser<'t> {
     }
 
     // s-flow-line-prefix(n)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn flow_line_prefix(&mut self, indent: u32) {
         let start = self.pos();
-        for _ in 0..indent {
+        for found in 0..indent {
             if !self.eat_char(' ') {
                 return self.error(
                     start,
-                    format!("expected line to be indented {indent} spaces"),
+                    ParseError::BadIndent {
+                        expected: indent,
+                        found,
+                    },
                     is_flow_indicator,
                 );
             }
@@ -279,6 +588,7 @@ impl<'t> Parser<'t> {
     }
 
     // c-ns-properties(n,c)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn properties(&mut self, indent: u32, context: Context) {
         if self.is_char('!') {
             self.tag_property();
@@ -295,21 +605,26 @@ impl<'t> Parser<'t> {
                 self.tag_property();
             }
         } else {
-            self.error(self.pos(), "expected '!' or '&'", context.recovery_fn());
+            self.expected(
+                self.pos(),
+                [Expected::Char('!'), Expected::Char('&')],
+                context.recovery_fn(),
+            );
         }
     }
 
     // l-directive
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn directive(&mut self) {
         let start = self.marker();
 
         if !self.eat_char('%') {
-            return self.error(self.pos(), "expected '%'", is_break);
+            return self.expected(self.pos(), [Expected::Char('%')], is_break);
         }
         self.token(DirectiveToken, start.pos);
 
         if !self.is(is_non_whitespace) {
-            return self.error(self.pos(), "expected directive name", is_break);
+            return self.expected(self.pos(), [Expected::DirectiveName], is_break);
         }
 
         let inner = self.marker();
@@ -318,20 +633,20 @@ impl<'t> Parser<'t> {
 
         if self.get(name.clone()) == "YAML" {
             if !self.try_inline_separator() {
-                return self.error(self.pos(), "expected YAML version", is_break);
+                return self.expected(self.pos(), [Expected::YamlVersion], is_break);
             }
 
             self.yaml_version();
             self.node_at(inner, YamlDirective);
         } else if self.get(name) == "TAG" {
             if !self.try_inline_separator() {
-                return self.error(self.pos(), "expected tag handle", is_break);
+                return self.expected(self.pos(), [Expected::TagHandle], is_break);
             }
 
             self.tag_handle();
 
             if !self.try_inline_separator() {
-                return self.error(self.pos(), "expected tag prefix", is_break);
+                return self.expected(self.pos(), [Expected::TagPrefix], is_break);
             }
 
             self.tag_prefix();
@@ -354,17 +669,18 @@ impl<'t> Parser<'t> {
     }
 
     // ns-yaml-version
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn yaml_version(&mut self) {
         let start = self.pos();
         if !self.is(is_dec_digit) {
-            return self.error(start, "invalid YAML version: expected digit", is_separator);
+            return self.expected(start, [Expected::Digit], is_separator);
         }
         self.eat_while(is_dec_digit);
         if !self.eat_char('.') {
-            return self.error(start, "invalid YAML version: expected '.'", is_separator);
+            return self.expected(start, [Expected::Char('.')], is_separator);
         }
         if !self.is(is_dec_digit) {
-            return self.error(start, "invalid YAML version: expected digit", is_separator);
+            return self.expected(start, [Expected::Digit], is_separator);
         }
         self.eat_while(is_dec_digit);
 
@@ -372,11 +688,16 @@ impl<'t> Parser<'t> {
     }
 
     // c-ns-alias-node
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn alias_node(&mut self) {
         let start = self.marker();
 
         if !self.eat_char('*') {
-            return self.error(self.pos(), "expected '*'", is_flow_indicator_or_separator);
+            return self.expected(
+                self.pos(),
+                [Expected::Char('*')],
+                is_flow_indicator_or_separator,
+            );
         }
         self.token(AliasToken, start.pos);
 
@@ -386,11 +707,16 @@ impl<'t> Parser<'t> {
     }
 
     // c-ns-anchor-property
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn anchor_property(&mut self) {
         let start = self.marker();
 
         if !self.eat_char('&') {
-            return self.error(self.pos(), "expected '*'", is_flow_indicator_or_separator);
+            return self.expected(
+                self.pos(),
+                [Expected::Char('&')],
+                is_flow_indicator_or_separator,
+            );
         }
         self.token(AnchorToken, start.pos);
 
@@ -399,11 +725,12 @@ impl<'t> Parser<'t> {
         self.node_at(start, AnchorProperty)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn anchor_name(&mut self) {
         if !self.is(is_anchor_char) {
-            return self.error(
+            return self.expected(
                 self.pos(),
-                "invalid anchor name character",
+                [Expected::AnchorName],
                 is_flow_indicator_or_separator,
             );
         }
@@ -413,14 +740,11 @@ impl<'t> Parser<'t> {
     }
 
     // c-tag-handle
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn tag_handle(&mut self) {
         let start = self.pos();
         if !self.eat_char('!') {
-            return self.error(
-                start,
-                "invalid tag handle: expected '!'",
-                is_flow_indicator_or_separator,
-            );
+            return self.expected(start, [Expected::Char('!')], is_flow_indicator_or_separator);
         }
 
         if self.is(is_word_char) {
@@ -428,9 +752,9 @@ impl<'t> Parser<'t> {
             let name = self.eat_while(is_word_char);
             self.token(NamedTagHandle, name.start);
             if !self.eat_char('!') {
-                return self.error(
+                return self.expected(
                     name.end,
-                    "invalid tag handle: expected '!'",
+                    [Expected::Char('!')],
                     is_flow_indicator_or_separator,
                 );
             }
@@ -443,12 +767,13 @@ impl<'t> Parser<'t> {
     }
 
     // ns-tag-prefix
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn tag_prefix(&mut self) {
         let start = self.pos();
         if self.eat_char('!') {
             self.token(TagToken, start);
         } else if !self.is(is_uri_char) || self.is(is_flow_indicator) {
-            return self.error(start, "invalid initial tag prefix character", is_separator);
+            return self.expected(start, [Expected::TagPrefix], is_separator);
         }
 
         let prefix = self.eat_while(is_uri_char);
@@ -457,19 +782,24 @@ impl<'t> Parser<'t> {
     }
 
     // c-ns-tag-property
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn tag_property(&mut self) {
         let start = self.marker();
         if !self.eat_char('!') {
-            return self.error(start.pos, "expected '!'", is_flow_indicator_or_separator);
+            return self.expected(
+                start.pos,
+                [Expected::Char('!')],
+                is_flow_indicator_or_separator,
+            );
         }
 
         if self.eat_char('<') {
             self.token(VerbatimTagStart, start.pos);
 
             if !self.is(is_uri_char) {
-                return self.error(
+                return self.expected(
                     self.pos(),
-                    "invalid verbatim tag character",
+                    [Expected::VerbatimTag],
                     is_flow_indicator_or_separator,
                 );
             }
@@ -478,7 +808,11 @@ impl<'t> Parser<'t> {
             self.token(VerbatimTag, uri.start);
 
             if !self.eat_char('>') {
-                return self.error(self.pos(), "expected '>'", is_flow_indicator_or_separator);
+                return self.expected(
+                    self.pos(),
+                    [Expected::Char('>')],
+                    is_flow_indicator_or_separator,
+                );
             }
             self.token(VerbatimTagEnd, uri.end);
         } else if self.is(is_tag_char) {
@@ -490,10 +824,17 @@ impl<'t> Parser<'t> {
                     self.token_at(NamedTagHandle, name_or_suffix.clone());
                 } else {
                     self.token_at(Error, name_or_suffix.clone());
-                    self.diagnostics.push(Diagnostic::new(
+                    let found = self
+                        .get(name_or_suffix.clone())
+                        .chars()
+                        .find(|&ch| !is_word_char(ch))
+                        .unwrap();
+                    self.diagnostics.push(Diagnostic::from_parse_error(
                         name_or_suffix.clone(),
-                        Severity::Error,
-                        "invalid character in tag handle",
+                        ParseError::InvalidCharacter {
+                            found,
+                            expected: Expected::TagHandle,
+                        },
                     ));
                 }
 
@@ -513,11 +854,12 @@ impl<'t> Parser<'t> {
         self.node_at(start, TagProperty);
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     fn tag_suffix(&mut self) {
         if !self.is(is_tag_char) {
-            return self.error(
+            return self.expected(
                 self.pos(),
-                "expected tag suffix",
+                [Expected::TagSuffix],
                 is_flow_indicator_or_separator,
             );
         }
@@ -527,7 +869,7 @@ impl<'t> Parser<'t> {
     }
 
     fn peek_skip_inline_separator(&self) -> Option<char> {
-        let mut peek = self.iter.clone();
+        let mut peek = self.rest();
         loop {
             match peek.next() {
                 Some(ch) if is_whitespace(ch) => continue,
@@ -546,7 +888,7 @@ impl<'t> Parser<'t> {
     }
 
     fn peek_skip_line_separator(&self) -> Option<char> {
-        let mut peek = self.iter.clone();
+        let mut peek = self.rest();
         loop {
             match peek.next() {
                 Some(ch) if is_separator(ch) => continue,
@@ -626,7 +968,7 @@ impl<'t> Parser<'t> {
 
     // <start-of-line>
     fn is_start_of_line(&self) -> bool {
-        match self.text[..self.pos()].chars().last() {
+        match self.text[..self.pos].chars().next_back() {
             Some(ch) if is_break(ch) => true,
             Some(_) => false,
             None => true,
@@ -660,27 +1002,66 @@ impl<'t> Parser<'t> {
             self.bump();
             true
         } else {
+            self.note_expected(Expected::Char(ch));
             false
         }
     }
 
+    /// Records that `expected` would have been valid at the current position.
+    fn note_expected(&mut self, expected: Expected) {
+        if self.expected_pos != self.pos() {
+            self.expected.clear();
+            self.expected_pos = self.pos();
+        }
+        if !self.expected.contains(&expected) {
+            self.expected.push(expected);
+        }
+    }
+
     fn eat_while(&mut self, pred: impl Fn(char) -> bool) -> Span {
         let start = self.pos();
-        while self.is(&pred) {
-            self.bump();
+        let rest = &self.text[start..];
+        let end = start + rest.find(|ch| !pred(ch)).unwrap_or(rest.len());
+        if end != start {
+            self.seek(end);
         }
-        let end = self.pos();
         start..end
     }
 
-    fn error(&mut self, start: usize, message: impl ToString, recover_pred: impl Fn(char) -> bool) {
+    fn expected(
+        &mut self,
+        start: usize,
+        expected: impl Into<Vec<Expected>>,
+        recover_pred: impl Fn(char) -> bool,
+    ) {
+        for expected in expected.into() {
+            self.note_expected(expected);
+        }
+        let error = ParseError::UnexpectedToken {
+            expected: self.expected.clone(),
+            found: self.peek(),
+        };
+        self.error(start, error, recover_pred)
+    }
+
+    fn error(&mut self, start: usize, error: ParseError, recover_pred: impl Fn(char) -> bool) {
         while !self.is(&recover_pred) && !self.is_end_of_input() {
             self.bump();
         }
         let span = start..self.pos();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(span = ?span, %error, "recovered from syntax error");
         self.token_at(Error, span.clone());
         self.diagnostics
-            .push(Diagnostic::new(span, Severity::Error, message));
+            .push(Diagnostic::from_parse_error(span, error));
+    }
+
+    /// Adds any remaining input, which the grammar does not cover yet, as an error token so the tree stays lossless.
+    fn unparsed(&mut self) {
+        if !self.is_end_of_input() {
+            let rest = self.eat_while(|_| true);
+            self.token_at(Error, rest);
+        }
     }
 
     fn token(&mut self, kind: SyntaxKind, start: usize) {
@@ -688,7 +1069,25 @@ impl<'t> Parser<'t> {
     }
 
     fn token_at(&mut self, kind: SyntaxKind, span: Span) {
-        self.builder.token(kind.into(), &self.text[span])
+        #[cfg(feature = "tracing")]
+        tracing::trace!(?kind, text = &self.text[span.clone()], "token");
+        self.builder.token(kind.into(), &self.text[span]);
+
+        if !self.cancelled
+            && self
+                .cancellation
+                .is_some_and(CancellationToken::is_cancelled)
+        {
+            self.cancel();
+        }
+    }
+
+    /// Truncates the input at the current position, so every rule sees the end of input and returns promptly.
+    fn cancel(&mut self) {
+        self.cancelled = true;
+        self.text = &self.text[..self.pos];
+        self.iter = "".chars();
+        self.peeked = None;
     }
 
     fn get(&self, span: Span) -> &str {
@@ -703,6 +1102,8 @@ impl<'t> Parser<'t> {
     }
 
     fn node_at(&mut self, marker: Marker, kind: SyntaxKind) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(?kind, span = ?(marker.pos..self.pos()), "node");
         self.builder.start_node_at(marker.checkpoint, kind.into());
         self.builder.finish_node();
     }
@@ -717,22 +1118,39 @@ impl<'t> Parser<'t> {
             panic!("detected infinite loop in parser");
         }
 
-        self.iter.clone().next()
+        self.peeked
     }
 
     fn peek_next(&self) -> Option<char> {
-        self.iter.clone().nth(2)
+        self.iter.clone().nth(1)
     }
 
     fn bump(&mut self) {
         #[cfg(debug_assertions)]
         self.peek_count
             .store(0, std::sync::atomic::Ordering::Relaxed);
-        self.iter.next().expect("called bump at end of input");
+        let ch = self.peeked.expect("called bump at end of input");
+        self.pos += ch.len_utf8();
+        self.peeked = self.iter.next();
+    }
+
+    fn seek(&mut self, pos: usize) {
+        debug_assert!(pos > self.pos);
+
+        #[cfg(debug_assertions)]
+        self.peek_count
+            .store(0, std::sync::atomic::Ordering::Relaxed);
+        self.pos = pos;
+        self.iter = self.text[pos..].chars();
+        self.peeked = self.iter.next();
     }
 
     fn pos(&self) -> usize {
-        self.text.len() - self.iter.as_str().len()
+        self.pos
+    }
+
+    fn rest(&self) -> impl Iterator<Item = char> + 't {
+        self.peeked.into_iter().chain(self.iter.clone())
     }
 }
 
@@ -1,18 +1,40 @@
 use insta::assert_debug_snapshot;
+use rowan::GreenTokenData;
 
-use super::Parser;
+use super::{
+    isolate_parse, parse_cancellable, parse_with_cache, Context, Encoding, Expected, InvalidData,
+    Parse, ParseCache, ParseError, ParseOptions, Parser,
+};
+use crate::{CancellationToken, Cancelled};
 
 macro_rules! case {
     ($method:ident($source:expr)) => {{
+        #[cfg(feature = "tracing")]
+        let _trace = trace();
         let mut parser = Parser::new($source);
         parser.$method();
         let end = parser.pos();
         let parse = parser.finish();
         assert_debug_snapshot!(parse);
-        assert_eq!(parse.node.to_string(), $source[..end]);
+        assert_eq!(parse.syntax().to_string(), $source[..end]);
     }};
 }
 
+/// Logs each grammar rule entered and exited, each token and node built, and each error recovery. Test output is
+/// only shown for failing tests, so this gives a trace of how the parser reached an unexpected snapshot.
+#[cfg(feature = "tracing")]
+fn trace() -> tracing::subscriber::DefaultGuard {
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_span_events(FmtSpan::ENTER | FmtSpan::EXIT)
+        .with_test_writer()
+        .without_time()
+        .finish();
+    tracing::subscriber::set_default(subscriber)
+}
+
 #[test]
 pub fn directive() {
     case!(directive(""));
@@ -114,3 +136,159 @@ fn tag_property() {
     case!(tag_property("!!(bar)"));
     case!(tag_property("!![bar]"));
 }
+
+#[test]
+fn shared_cache() {
+    fn tokens(parse: &Parse) -> Vec<*const GreenTokenData> {
+        parse
+            .syntax()
+            .descendants_with_tokens()
+            .filter_map(|element| element.into_token())
+            .map(|token| token.green() as *const GreenTokenData)
+            .collect()
+    }
+
+    let mut cache = ParseCache::default();
+    let first = parse_with_cache(b"%YAML 1.2 #comment", &mut cache);
+    let second = parse_with_cache(b"%YAML 1.2 #comment", &mut cache);
+    assert_eq!(tokens(&first), tokens(&second));
+
+    let third = parse_with_cache(b"%YAML 1.2 #comment", &mut ParseCache::default());
+    assert!(tokens(&first)
+        .iter()
+        .zip(tokens(&third))
+        .all(|(&first, third)| first != third));
+}
+
+#[test]
+fn cancellation() {
+    let cancellation = CancellationToken::new();
+    let parse = parse_cancellable(b"%YAML 1.2", &mut ParseCache::default(), &cancellation);
+    assert_eq!(parse.unwrap().syntax().to_string(), "%YAML 1.2");
+
+    cancellation.cancel();
+    let parse = parse_cancellable(b"%YAML 1.2", &mut ParseCache::default(), &cancellation);
+    assert_eq!(parse.unwrap_err(), Cancelled);
+}
+
+#[test]
+fn cancellation_stops_consuming_input() {
+    let cancellation = CancellationToken::new();
+    let mut parser = Parser::new("%YAML 1.2 # comment");
+    parser.cancellation = Some(&cancellation);
+
+    cancellation.cancel();
+    parser.directive();
+    assert!(parser.cancelled);
+    assert_eq!(parser.finish().syntax().to_string(), "%");
+}
+
+#[test]
+fn isolate_todo() {
+    // Flow sequences are not implemented yet, so this reaches a `todo!()` in the parser.
+    let parse = isolate_parse(&ParseOptions::default(), || {
+        let mut parser = Parser::new("[a]");
+        parser.flow_node(0, Context::FlowIn);
+        Ok(parser.finish())
+    })
+    .unwrap();
+
+    assert_eq!(parse.errors().len(), 1);
+    assert_eq!(
+        parse.errors()[0].message(),
+        "internal error: the analyzer panicked while parsing this file: not yet implemented"
+    );
+    assert!(parse.errors()[0].notes()[0].starts_with("panicked at "));
+    assert!(parse.errors()[0].notes()[0].contains("mod.rs"));
+    assert_eq!(parse.text(), "");
+}
+
+#[test]
+fn lossless() {
+    let text = "%YAML 1.2\nsteps:\n- script: echo\n";
+    let parse = super::parse(text.as_bytes());
+    assert_eq!(parse.text(), text);
+    assert!(parse
+        .debug_dump()
+        .contains(r#"Error@10..32 "steps:\n- script: echo\n""#));
+
+    assert_eq!(super::parse(b"\xff").text(), "");
+}
+
+#[test]
+fn parse_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Parse>();
+
+    let parse = super::parse(b"%YAML 1.2");
+    let text = std::thread::spawn(move || parse.syntax().to_string())
+        .join()
+        .unwrap();
+    assert_eq!(text, "%YAML 1.2");
+}
+
+#[test]
+fn debug_dump() {
+    insta::assert_snapshot!(super::parse(b"%YAML 1.2").debug_dump());
+}
+
+#[test]
+fn parse_error() {
+    let parse = super::parse(b"%YAML 1.x");
+    assert_eq!(
+        parse.errors()[0].parse_error(),
+        Some(&ParseError::UnexpectedToken {
+            expected: vec![Expected::Digit],
+            found: Some('x'),
+        })
+    );
+
+    let parse = super::parse(b"\xff\xfeX");
+    assert_eq!(
+        parse.errors()[0].parse_error(),
+        Some(&ParseError::InvalidEncoding {
+            encoding: Encoding::Utf16Le,
+            reason: InvalidData::Truncated,
+        })
+    );
+    assert_eq!(parse.errors()[0].span(), 2..3);
+    assert_eq!(
+        parse.errors()[0].message(),
+        "source file was not valid utf-16le: incomplete character at end of file"
+    );
+
+    let error = ParseError::UnexpectedToken {
+        expected: vec![
+            Expected::Char('['),
+            Expected::Char('{'),
+            Expected::FlowContent,
+        ],
+        found: None,
+    };
+    assert_eq!(
+        error.to_string(),
+        "expected one of '[', '{' or flow content, found end of input"
+    );
+}
+
+#[test]
+fn expected_set() {
+    let parse = super::parse(b"%YAML 1.2 x");
+    assert_eq!(
+        parse.errors()[0].parse_error(),
+        Some(&ParseError::UnexpectedToken {
+            expected: vec![Expected::Comment, Expected::EndOfLine],
+            found: Some('x'),
+        })
+    );
+
+    // Alternatives tried at an earlier position are not included.
+    let parse = super::parse(b"%YAML 1.2x");
+    assert_eq!(
+        parse.errors()[0].parse_error(),
+        Some(&ParseError::UnexpectedToken {
+            expected: vec![Expected::EndOfLine],
+            found: Some('x'),
+        })
+    );
+}
@@ -0,0 +1,232 @@
+//! Discovery of pipeline and template files in a repository.
+
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+
+/// Glob patterns used to classify files found during discovery.
+///
+/// Patterns are matched against paths relative to the workspace root, using `/` as the separator. As with
+/// `.gitignore` files, `*` does not match `/`, and `**/` matches any number of directories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    /// Patterns matching pipeline entry points.
+    pub pipelines: Vec<String>,
+    /// Patterns matching template files, which are only analyzed when included from a pipeline.
+    pub templates: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FileKind {
+    Pipeline,
+    Template,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveredFile {
+    pub path: PathBuf,
+    pub kind: FileKind,
+}
+
+#[derive(Debug)]
+pub enum DiscoveryError {
+    Glob(globset::Error),
+    Walk(ignore::Error),
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig {
+            pipelines: vec![
+                "**/azure-pipelines.{yml,yaml}".to_owned(),
+                "**/*.pipeline.{yml,yaml}".to_owned(),
+            ],
+            templates: vec![
+                "**/templates/**/*.{yml,yaml}".to_owned(),
+                "**/*.template.{yml,yaml}".to_owned(),
+            ],
+        }
+    }
+}
+
+/// Finds pipeline and template files under `root`, skipping files excluded by `.gitignore`.
+///
+/// Files matching both a pipeline and a template pattern are classified as pipelines. Files matching neither are
+/// not returned. Results are sorted by path.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(root = %root.as_ref().display()))
+)]
+pub fn discover(
+    root: impl AsRef<Path>,
+    config: &DiscoveryConfig,
+) -> Result<Vec<DiscoveredFile>, DiscoveryError> {
+    let root = root.as_ref();
+    let pipelines = build_glob_set(&config.pipelines)?;
+    let templates = build_glob_set(&config.templates)?;
+
+    let walk = WalkBuilder::new(root)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walk {
+        let entry = entry.map_err(DiscoveryError::Walk)?;
+        if !entry.file_type().is_some_and(|ty| ty.is_file()) {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let kind = if pipelines.is_match(relative) {
+            FileKind::Pipeline
+        } else if templates.is_match(relative) {
+            FileKind::Template
+        } else {
+            continue;
+        };
+
+        files.push(DiscoveredFile {
+            path: entry.into_path(),
+            kind,
+        });
+    }
+
+    files.sort_by(|l, r| l.path.cmp(&r.path));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(files = files.len(), "discovered files");
+    Ok(files)
+}
+
+pub(crate) fn build_glob_set(patterns: &[String]) -> Result<GlobSet, DiscoveryError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(DiscoveryError::Glob)?;
+        builder.add(glob);
+    }
+    builder.build().map_err(DiscoveryError::Glob)
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscoveryError::Glob(err) => write!(f, "invalid glob pattern: {err}"),
+            DiscoveryError::Walk(_) => write!(f, "failed to search workspace directory"),
+        }
+    }
+}
+
+impl Error for DiscoveryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DiscoveryError::Glob(err) => Some(err),
+            DiscoveryError::Walk(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn workspace(files: &[&str]) -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = root.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        root
+    }
+
+    fn relative(root: &Path, files: Vec<DiscoveredFile>) -> Vec<(String, FileKind)> {
+        files
+            .into_iter()
+            .map(|file| {
+                let path = file.path.strip_prefix(root).unwrap();
+                (path.to_str().unwrap().replace('\\', "/"), file.kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn discover_default() {
+        let root = workspace(&[
+            "azure-pipelines.yml",
+            "build/release.pipeline.yaml",
+            "build/templates/steps.yml",
+            "build/templates/nested/jobs.yaml",
+            "deploy/deploy.template.yml",
+            "docker-compose.yml",
+            ".azure-pipelines/templates/hidden.yml",
+            ".git/templates/ignored.yml",
+            "ignored/templates/ignored.yml",
+            ".gitignore",
+        ]);
+        let root = root.path();
+        fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+
+        let files = discover(root, &DiscoveryConfig::default()).unwrap();
+        assert_eq!(
+            relative(root, files),
+            [
+                (
+                    ".azure-pipelines/templates/hidden.yml".to_owned(),
+                    FileKind::Template
+                ),
+                ("azure-pipelines.yml".to_owned(), FileKind::Pipeline),
+                ("build/release.pipeline.yaml".to_owned(), FileKind::Pipeline),
+                (
+                    "build/templates/nested/jobs.yaml".to_owned(),
+                    FileKind::Template
+                ),
+                ("build/templates/steps.yml".to_owned(), FileKind::Template),
+                ("deploy/deploy.template.yml".to_owned(), FileKind::Template),
+            ]
+        );
+    }
+
+    #[test]
+    fn discover_custom() {
+        let root = workspace(&["ci/main.yml", "ci/shared/steps.yml"]);
+        let root = root.path();
+
+        let config = DiscoveryConfig {
+            pipelines: vec!["ci/*.yml".to_owned()],
+            templates: vec!["ci/shared/*.yml".to_owned()],
+        };
+        let files = discover(root, &config).unwrap();
+        assert_eq!(
+            relative(root, files),
+            [
+                ("ci/main.yml".to_owned(), FileKind::Pipeline),
+                ("ci/shared/steps.yml".to_owned(), FileKind::Template),
+            ]
+        );
+    }
+
+    #[test]
+    fn discover_invalid_glob() {
+        let config = DiscoveryConfig {
+            pipelines: vec!["ci/[.yml".to_owned()],
+            templates: vec![],
+        };
+        let err = discover(".", &config).unwrap_err();
+        assert!(matches!(err, DiscoveryError::Glob(_)));
+    }
+}
@@ -0,0 +1,29 @@
+//! Golden-file tests which snapshot the syntax tree and diagnostics for each pipeline in `tests/corpus`.
+//!
+//! To add a regression case from a bug report, run
+//!
+//! ```sh
+//! cargo run --example corpus_case -- path/to/azure-pipelines.yml [name]
+//! ```
+//!
+//! which copies the file into the corpus and records its snapshot for review.
+
+use std::fs;
+
+use azure_pipelines_analyzer::syntax::parse;
+
+#[test]
+fn corpus() {
+    insta::glob!("corpus/*.{yml,yaml}", |path| {
+        let text = fs::read(path).unwrap();
+        let parse = parse(&text);
+        assert_eq!(
+            parse.text().as_bytes(),
+            text,
+            "syntax tree for '{}' does not preserve the input",
+            path.display()
+        );
+        let snapshot = format!("{}\n{:#?}", parse.debug_dump(), parse.errors());
+        insta::assert_snapshot!(snapshot);
+    });
+}